@@ -0,0 +1,361 @@
+use crate::defs::{Cell, Coord, GameResult, Grid, Minigrid, Tiebreak, LINES_3};
+use crate::rules::Ruleset;
+use rand::Rng;
+
+/// Mask with all the 9 cells of a 3x3 board set
+pub const FULL_MASK: u16 = 0x1FF;
+
+const fn build_line_masks() -> [u16; 8] {
+    let mut masks = [0; 8];
+    let mut l = 0;
+    while l < LINES_3.len() {
+        masks[l] = (1 << LINES_3[l][0]) | (1 << LINES_3[l][1]) | (1 << LINES_3[l][2]);
+        l += 1;
+    }
+    masks
+}
+/// `LINES_3` as bitmasks
+pub const LINE_MASKS: [u16; 8] = build_line_masks();
+
+const fn build_win_table() -> [bool; 512] {
+    let mut table = [false; 512];
+    let mut mask = 0;
+    while mask < 512 {
+        let mut l = 0;
+        while l < LINE_MASKS.len() {
+            if mask as u16 & LINE_MASKS[l] == LINE_MASKS[l] {
+                table[mask] = true;
+            }
+            l += 1;
+        }
+        mask += 1;
+    }
+    table
+}
+/// `WIN_TABLE[mask]` is true if the 9-bit `mask` contains a full line
+pub static WIN_TABLE: [bool; 512] = build_win_table();
+
+/// Bitboard representation of a `Grid`.
+/// Bit `x + 3 * y` of a mask is the cell (x, y) of a 3x3 board, both for minigrids and the meta board.
+#[derive(Clone, Copy, Debug, PartialEq, Hash, Eq, Default)]
+pub struct BitBoard {
+    /// Cells taken by each player, indexed by [player][minigrid]
+    pub cells: [[u16; 9]; 2],
+    /// Minigrids won by each player
+    pub won: [u16; 2],
+    /// Minigrids closed without a winner
+    pub drawn: u16,
+}
+
+/// Index of `symbol` in the per-player arrays of `BitBoard`
+#[inline]
+pub fn player_index(symbol: Cell) -> usize {
+    match symbol {
+        Cell::Cross => 0,
+        Cell::Circle => 1,
+        _ => panic!("Invalid player"),
+    }
+}
+#[inline]
+pub fn is_win(mask: u16) -> bool {
+    WIN_TABLE[mask as usize]
+}
+/// Meta line made of minigrids in `own` or `drawn`, with at least one of them in `own`
+fn has_line_with_draws(own: u16, drawn: u16) -> bool {
+    LINE_MASKS
+        .iter()
+        .any(|&line| (own | drawn) & line == line && own & line != 0)
+}
+
+impl BitBoard {
+    #[inline]
+    pub fn get(&self, coord: Coord) -> Cell {
+        let bit = 1 << coord.cell_index();
+        let meta = coord.meta_index();
+        if self.cells[0][meta] & bit != 0 {
+            Cell::Cross
+        } else if self.cells[1][meta] & bit != 0 {
+            Cell::Circle
+        } else {
+            Cell::Empty
+        }
+    }
+    #[inline]
+    pub fn set(&mut self, coord: Coord, symbol: Cell) {
+        let bit = 1 << coord.cell_index();
+        let meta = coord.meta_index();
+        self.cells[0][meta] &= !bit;
+        self.cells[1][meta] &= !bit;
+        if symbol != Cell::Empty {
+            self.cells[player_index(symbol)][meta] |= bit;
+        }
+    }
+    /// Cells of a minigrid taken by either player
+    #[inline]
+    pub fn occupied(&self, meta_index: usize) -> u16 {
+        self.cells[0][meta_index] | self.cells[1][meta_index]
+    }
+    /// Minigrids that are neither won nor drawn
+    #[inline]
+    pub fn open_mask(&self) -> u16 {
+        !(self.won[0] | self.won[1] | self.drawn) & FULL_MASK
+    }
+    /// Minigrids with no empty cell
+    pub fn full_mask(&self) -> u16 {
+        (0..9)
+            .filter(|&meta| self.occupied(meta) == FULL_MASK)
+            .fold(0, |mask, meta| mask | 1 << meta)
+    }
+    /// Minigrids that can still be played in under `rules`
+    #[inline]
+    pub fn playable_mask(&self, rules: Ruleset) -> u16 {
+        if rules.play_in_won_minigrids {
+            !self.full_mask() & FULL_MASK
+        } else {
+            self.open_mask()
+        }
+    }
+    /// Status of a minigrid, using the same encoding as `Grid::completed_minigrid`
+    pub fn minigrid_status(&self, meta_index: usize) -> Cell {
+        let bit = 1 << meta_index;
+        if self.won[0] & bit != 0 {
+            Cell::Cross
+        } else if self.won[1] & bit != 0 {
+            Cell::Circle
+        } else if self.drawn & bit != 0 {
+            Cell::Draw
+        } else {
+            Cell::Empty
+        }
+    }
+    pub fn update_grid(&mut self) {
+        let open = self.open_mask();
+        for meta in 0..9 {
+            let bit = 1 << meta;
+            if open & bit == 0 {
+                continue;
+            }
+            if is_win(self.cells[0][meta]) {
+                self.won[0] |= bit;
+            } else if is_win(self.cells[1][meta]) {
+                self.won[1] |= bit;
+            } else if self.occupied(meta) == FULL_MASK {
+                self.drawn |= bit;
+            }
+        }
+    }
+    /// Play `symbol` at `coord` (which must be in a playable minigrid), re-checking only the touched minigrid
+    #[inline]
+    pub fn make_move(&mut self, coord: Coord, symbol: Cell) {
+        let meta = coord.meta_index();
+        let player = player_index(symbol);
+        self.cells[player][meta] |= 1 << coord.cell_index();
+
+        if self.open_mask() & (1 << meta) == 0 {
+            // A won minigrid keeps its winner (only playable with `Ruleset::play_in_won_minigrids`)
+            return;
+        }
+        if is_win(self.cells[player][meta]) {
+            self.won[player] |= 1 << meta;
+        } else if self.occupied(meta) == FULL_MASK {
+            self.drawn |= 1 << meta;
+        }
+    }
+    /// Take back the last move played in `coord`'s minigrid with `make_move`
+    #[inline]
+    pub fn unmake_move(&mut self, coord: Coord) {
+        let meta = coord.meta_index();
+        let bit = !(1 << coord.cell_index());
+        self.cells[0][meta] &= bit;
+        self.cells[1][meta] &= bit;
+
+        // Reopen the minigrid if this move closed it: a minigrid won before the move still has its line
+        let meta_bit = !(1 << meta);
+        for player in 0..2 {
+            if !is_win(self.cells[player][meta]) {
+                self.won[player] &= meta_bit;
+            }
+        }
+        self.drawn &= meta_bit;
+    }
+    /// Whether (Cross, Circle) own a full meta line
+    #[inline]
+    pub fn meta_lines(&self, rules: Ruleset) -> (bool, bool) {
+        if rules.draw_counts_for_both {
+            (
+                has_line_with_draws(self.won[0], self.drawn),
+                has_line_with_draws(self.won[1], self.drawn),
+            )
+        } else {
+            (is_win(self.won[0]), is_win(self.won[1]))
+        }
+    }
+    /// Whether `symbol` can still win the game, with a meta line or on the minigrid count
+    pub fn can_still_win(&self, symbol: Cell, rules: Ruleset) -> bool {
+        if rules.tiebreak == Tiebreak::MostMinigrids {
+            return true;
+        }
+        // Minigrids that can't be part of a meta line of `symbol` anymore
+        let mut blocked = self.won[1 - player_index(symbol)];
+        if !rules.draw_counts_for_both {
+            blocked |= self.drawn;
+        }
+        LINE_MASKS
+            .iter()
+            .any(|&line| line & blocked == 0 && line & !self.drawn != 0)
+    }
+    pub fn result(&self, rules: Ruleset) -> GameResult {
+        rules.tiebreak.result(
+            self.meta_lines(rules),
+            self.playable_mask(rules) != 0,
+            (self.won[0].count_ones(), self.won[1].count_ones()),
+        )
+    }
+
+    /// Minigrids the next player may play in, given the last move
+    #[inline]
+    pub fn allowed_mask(&self, last_move: Option<Coord>, rules: Ruleset) -> u16 {
+        let playable = self.playable_mask(rules);
+        match last_move {
+            Some(m) if playable & (1 << m.cell_index()) != 0 => 1 << m.cell_index(),
+            // If target meta grid is closed (or on the first move), player can choose any available
+            _ => playable,
+        }
+    }
+    /// Get all valid legal moves, in the same order as `Grid::get_legal_moves`
+    pub fn get_legal_moves(&self, last_move: Option<Coord>, rules: Ruleset) -> Vec<Coord> {
+        self.moves_in(self.allowed_mask(last_move, rules))
+    }
+    /// Empty cells of the minigrids in `allowed`
+    pub fn moves_in(&self, allowed: u16) -> Vec<Coord> {
+        self.move_list_in(allowed).to_vec()
+    }
+    /// Same as `moves_in`, without allocating
+    pub fn move_list_in(&self, mut allowed: u16) -> MoveList {
+        let mut moves = MoveList::default();
+        while allowed != 0 {
+            let meta = allowed.trailing_zeros() as usize;
+            allowed &= allowed - 1;
+
+            let mut free = !self.occupied(meta) & FULL_MASK;
+            while free != 0 {
+                let cell = free.trailing_zeros() as usize;
+                free &= free - 1;
+                moves.push(Coord::from_indices(meta, cell));
+            }
+        }
+        moves
+    }
+    /// Number of empty cells in the minigrids in `allowed`
+    #[inline]
+    pub fn count_moves_in(&self, mut allowed: u16) -> u32 {
+        let mut count = 0;
+        while allowed != 0 {
+            let meta = allowed.trailing_zeros() as usize;
+            allowed &= allowed - 1;
+            count += 9 - self.occupied(meta).count_ones();
+        }
+        count
+    }
+    /// The `n`-th move of `moves_in(allowed)`, without building the list
+    #[inline]
+    pub fn nth_move_in(&self, mut allowed: u16, mut n: u32) -> Option<Coord> {
+        while allowed != 0 {
+            let meta = allowed.trailing_zeros() as usize;
+            allowed &= allowed - 1;
+
+            let mut free = !self.occupied(meta) & FULL_MASK;
+            let count = free.count_ones();
+            if n >= count {
+                n -= count;
+                continue;
+            }
+            for _ in 0..n {
+                free &= free - 1;
+            }
+            return Some(Coord::from_indices(meta, free.trailing_zeros() as usize));
+        }
+        None
+    }
+    /// Uniformly random move of `moves_in(allowed)`, `None` if there are no moves
+    #[inline]
+    pub fn random_move_in<R: Rng>(&self, allowed: u16, rng: &mut R) -> Option<Coord> {
+        let count = self.count_moves_in(allowed);
+        if count == 0 {
+            return None;
+        }
+        self.nth_move_in(allowed, rng.gen_range(0..count))
+    }
+}
+
+/// Fixed-capacity list of moves, stored inline (a position never has more than 81 moves)
+#[derive(Clone, Copy, Debug)]
+pub struct MoveList {
+    moves: [Coord; 81],
+    len: u8,
+}
+
+impl Default for MoveList {
+    fn default() -> MoveList {
+        MoveList {
+            moves: [Coord::from_indices(0, 0); 81],
+            len: 0,
+        }
+    }
+}
+impl MoveList {
+    #[inline]
+    pub fn push(&mut self, coord: Coord) {
+        self.moves[self.len as usize] = coord;
+        self.len += 1;
+    }
+}
+impl std::ops::Deref for MoveList {
+    type Target = [Coord];
+
+    fn deref(&self) -> &[Coord] {
+        &self.moves[..self.len as usize]
+    }
+}
+impl<'a> IntoIterator for &'a MoveList {
+    type Item = &'a Coord;
+    type IntoIter = std::slice::Iter<'a, Coord>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl From<Grid> for BitBoard {
+    fn from(grid: Grid) -> BitBoard {
+        let mut board = BitBoard::default();
+        for (meta, minigrid) in grid.matrix.iter().enumerate() {
+            for (cell, &symbol) in minigrid.matrix.iter().enumerate() {
+                if symbol == Cell::Cross || symbol == Cell::Circle {
+                    board.cells[player_index(symbol)][meta] |= 1 << cell;
+                }
+            }
+            match grid.completed_minigrid[meta] {
+                Cell::Cross => board.won[0] |= 1 << meta,
+                Cell::Circle => board.won[1] |= 1 << meta,
+                Cell::Draw => board.drawn |= 1 << meta,
+                Cell::Empty => {}
+            }
+        }
+        board
+    }
+}
+impl From<BitBoard> for Grid {
+    fn from(board: BitBoard) -> Grid {
+        let mut grid = Grid::default();
+        for meta in 0..9 {
+            let mut minigrid = Minigrid::default();
+            for cell in 0..9 {
+                minigrid.matrix[cell] = board.get(Coord::from_indices(meta, cell));
+            }
+            grid.matrix[meta] = minigrid;
+            grid.completed_minigrid[meta] = board.minigrid_status(meta);
+        }
+        grid
+    }
+}
@@ -0,0 +1,172 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How much time players get for their moves
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum TimeControl {
+    /// No clock
+    #[default]
+    None,
+    /// The same time for every move, unused time is lost
+    PerMove(Duration),
+    /// A time bank for the whole game, which gets `increment` added after every move
+    Increment { base: Duration, increment: Duration },
+}
+
+/// Time a player has left when asked for a move
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeLeft {
+    pub control: TimeControl,
+    /// Time for this move with `TimeControl::PerMove`, otherwise what is left of the time bank
+    pub remaining: Duration,
+}
+
+impl TimeControl {
+    /// Time on the clock of each player before the first move
+    pub fn initial(self) -> Duration {
+        match self {
+            TimeControl::None => Duration::MAX,
+            TimeControl::PerMove(time) => time,
+            TimeControl::Increment { base, .. } => base,
+        }
+    }
+}
+
+impl TimeLeft {
+    /// Reasonable time to spend on the next move, leaving some margin for the overhead of the caller
+    pub fn move_budget(&self) -> Duration {
+        match self.control {
+            TimeControl::None => Duration::MAX,
+            TimeControl::PerMove(_) => self.remaining.mul_f64(0.9),
+            // A game lasts about 60 plies, so about 30 moves each; never use more than half of the bank
+            TimeControl::Increment { increment, .. } => {
+                (self.remaining / 30 + increment.mul_f64(0.9)).min(self.remaining / 2)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimeControlParseError(pub String);
+
+impl fmt::Display for TimeControlParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid time control '{}', expected 'none', '<seconds>/move' or '<seconds>+<increment>'",
+            self.0
+        )
+    }
+}
+impl std::error::Error for TimeControlParseError {}
+
+/// Written as `none`, `<seconds>/move` or `<base seconds>+<increment seconds>`
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TimeControl::None => write!(f, "none"),
+            TimeControl::PerMove(time) => write!(f, "{}/move", time.as_secs_f64()),
+            TimeControl::Increment { base, increment } => {
+                write!(f, "{}+{}", base.as_secs_f64(), increment.as_secs_f64())
+            }
+        }
+    }
+}
+impl FromStr for TimeControl {
+    type Err = TimeControlParseError;
+
+    fn from_str(text: &str) -> Result<TimeControl, TimeControlParseError> {
+        let error = || TimeControlParseError(text.to_string());
+        let seconds = |s: &str| {
+            s.parse::<f64>()
+                .ok()
+                .and_then(|s| Duration::try_from_secs_f64(s).ok())
+                .ok_or_else(error)
+        };
+        if text == "none" {
+            Ok(TimeControl::None)
+        } else if let Some(time) = text.strip_suffix("/move") {
+            Ok(TimeControl::PerMove(seconds(time)?))
+        } else if let Some((base, increment)) = text.split_once('+') {
+            Ok(TimeControl::Increment {
+                base: seconds(base)?,
+                increment: seconds(increment)?,
+            })
+        } else {
+            Err(error())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time_controls_round_trip() {
+        for control in [
+            TimeControl::None,
+            TimeControl::PerMove(Duration::from_millis(500)),
+            TimeControl::Increment {
+                base: Duration::from_secs(60),
+                increment: Duration::from_millis(250),
+            },
+        ] {
+            assert_eq!(control.to_string().parse(), Ok(control), "{control}");
+        }
+        assert_eq!(
+            "0.5/move".parse(),
+            Ok(TimeControl::PerMove(Duration::from_millis(500)))
+        );
+        assert_eq!(
+            "60+1".parse(),
+            Ok(TimeControl::Increment {
+                base: Duration::from_secs(60),
+                increment: Duration::from_secs(1),
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_time_controls_are_rejected() {
+        for text in ["", "5", "fast", "-1/move", "x/move", "60+", "+1", "60+-1"] {
+            assert_eq!(
+                text.parse::<TimeControl>(),
+                Err(TimeControlParseError(text.to_string())),
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn move_budgets() {
+        let budget = |control, remaining| TimeLeft { control, remaining }.move_budget();
+        assert_eq!(budget(TimeControl::None, Duration::MAX), Duration::MAX);
+
+        let per_move = TimeControl::PerMove(Duration::from_secs(1));
+        assert_eq!(
+            budget(per_move, Duration::from_secs(1)),
+            Duration::from_millis(900)
+        );
+
+        let increment = TimeControl::Increment {
+            base: Duration::from_secs(60),
+            increment: Duration::from_secs(1),
+        };
+        // A thirtieth of the bank plus most of the increment
+        assert_eq!(
+            budget(increment, Duration::from_secs(60)),
+            Duration::from_millis(2900)
+        );
+        assert_eq!(
+            budget(increment, Duration::from_secs(3)),
+            Duration::from_millis(1000)
+        );
+        // Never more than half of the bank
+        assert_eq!(
+            budget(increment, Duration::from_secs(1)),
+            Duration::from_millis(500)
+        );
+    }
+}
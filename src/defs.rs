@@ -1,9 +1,12 @@
+use crate::clock::TimeLeft;
+use crate::rules::Ruleset;
+use crate::state::GameState;
 use crossterm::{
     execute,
     terminal::{Clear, ClearType},
 };
 use std::fmt::Write as FmtWrite;
-use std::io::{self, stdout, Write as IoWrite};
+use std::io::stdout;
 
 #[derive(Clone, Copy, Debug, PartialEq, Hash, Eq, Default)]
 pub enum Cell {
@@ -11,6 +14,8 @@ pub enum Cell {
     Empty,
     Cross,
     Circle,
+    /// Only used in `Grid::completed_minigrid`: the minigrid is full and nobody won it
+    Draw,
 }
 #[derive(Clone, Copy, Debug, PartialEq, Hash, Eq)]
 pub struct Minigrid {
@@ -28,11 +33,54 @@ pub struct Coord {
     pub x: u8,
     pub y: u8,
 }
-#[derive(Clone, Copy, Debug)]
-pub struct MatchStats {
-    pub winner: Option<Cell>,
-    pub number_turns: u8,
-    pub final_grid: Grid,
+/// Outcome of a whole game, as seen from the meta board
+#[derive(Clone, Copy, Debug, PartialEq, Hash, Eq, Default)]
+pub enum GameResult {
+    #[default]
+    Ongoing,
+    Win(Cell),
+    Draw,
+}
+/// How to decide a game where every minigrid is closed but no meta line was made
+#[derive(Clone, Copy, Debug, PartialEq, Hash, Eq, Default)]
+pub enum Tiebreak {
+    /// The game is a draw
+    #[default]
+    None,
+    /// The player who won more minigrids wins; equal counts are a draw
+    MostMinigrids,
+}
+
+impl Tiebreak {
+    /// Result of a game from whether (Cross, Circle) own a full meta line, whether a minigrid can still
+    /// be played in, and how many minigrids (Cross, Circle) won
+    pub fn result(
+        self,
+        meta_lines: (bool, bool),
+        playable: bool,
+        minigrids: (u32, u32),
+    ) -> GameResult {
+        match meta_lines {
+            (true, false) => return GameResult::Win(Cell::Cross),
+            (false, true) => return GameResult::Win(Cell::Circle),
+            // Only possible when a drawn minigrid completes a line for both players
+            (true, true) => return GameResult::Draw,
+            (false, false) => {}
+        }
+        if playable {
+            return GameResult::Ongoing;
+        }
+
+        // No minigrid can be played anymore and there is no meta line
+        match self {
+            Tiebreak::None => GameResult::Draw,
+            Tiebreak::MostMinigrids => match minigrids.0.cmp(&minigrids.1) {
+                std::cmp::Ordering::Greater => GameResult::Win(Cell::Cross),
+                std::cmp::Ordering::Less => GameResult::Win(Cell::Circle),
+                std::cmp::Ordering::Equal => GameResult::Draw,
+            },
+        }
+    }
 }
 
 impl Default for Minigrid {
@@ -50,7 +98,50 @@ impl Default for Grid {
         }
     }
 }
+pub const LINES_3: [[usize; 3]; 8] = [
+    [0, 1, 2],
+    [3, 4, 5],
+    [6, 7, 8],
+    [0, 3, 6],
+    [1, 4, 7],
+    [2, 5, 8],
+    [0, 4, 8],
+    [2, 4, 6],
+];
+
+impl Coord {
+    pub fn from_indices(meta_index: usize, cell_index: usize) -> Coord {
+        Coord {
+            meta_x: (meta_index % 3) as u8,
+            meta_y: (meta_index / 3) as u8,
+            x: (cell_index % 3) as u8,
+            y: (cell_index / 3) as u8,
+        }
+    }
+    /// Index of the minigrid in `Grid::matrix`
+    pub fn meta_index(self) -> usize {
+        (self.meta_x + 3 * self.meta_y) as usize
+    }
+    /// Index of the cell inside its minigrid
+    pub fn cell_index(self) -> usize {
+        (self.x + 3 * self.y) as usize
+    }
+}
+impl GameResult {
+    pub fn is_over(self) -> bool {
+        self != GameResult::Ongoing
+    }
+    pub fn winner(self) -> Option<Cell> {
+        match self {
+            GameResult::Win(symbol) => Some(symbol),
+            _ => None,
+        }
+    }
+}
 impl Minigrid {
+    pub fn is_full(&self) -> bool {
+        self.matrix.iter().all(|&c| c != Cell::Empty)
+    }
     fn check(self, v1: usize, v2: usize, v3: usize) -> Option<Cell> {
         // All values must be non-empty and equal
         if self.matrix[v1] != Cell::Empty
@@ -69,73 +160,70 @@ impl Grid {
         self.matrix[minigrid_pos].matrix[grid_pos] = symbol;
     }
     pub fn update_grid(&mut self) {
-        let mut index: usize = 0;
-        for minigrid in self.matrix {
-            let mut results = Vec::new();
-
-            // Check rows
-            results.push(minigrid.check(0, 1, 2));
-            results.push(minigrid.check(3, 4, 5));
-            results.push(minigrid.check(6, 7, 8));
-
-            // Check columns
-            results.push(minigrid.check(0, 3, 6));
-            results.push(minigrid.check(1, 4, 7));
-            results.push(minigrid.check(2, 5, 8));
-
-            // Check diagonals
-            results.push(minigrid.check(0, 4, 8));
-            results.push(minigrid.check(2, 4, 6));
+        for (index, minigrid) in self.matrix.iter().enumerate() {
+            if self.completed_minigrid[index] != Cell::Empty {
+                continue;
+            }
 
-            for r in results {
-                if r.is_some() {
-                    let winner_cell: Cell = r.unwrap();
-                    self.completed_minigrid[index] = winner_cell;
-                    break;
-                }
+            // Check rows, columns and diagonals
+            if let Some(winner_cell) = LINES_3
+                .iter()
+                .find_map(|l| minigrid.check(l[0], l[1], l[2]))
+            {
+                self.completed_minigrid[index] = winner_cell;
+            } else if minigrid.is_full() {
+                // Nobody can win this minigrid anymore, so it's closed as a draw
+                self.completed_minigrid[index] = Cell::Draw;
             }
-            index += 1;
         }
     }
-    fn check_completed(self, v1: usize, v2: usize, v3: usize) -> Option<Cell> {
-        // All values must be non-empty and equal
-        if self.completed_minigrid[v1] != Cell::Empty
-            && self.completed_minigrid[v1] == self.completed_minigrid[v2]
-            && self.completed_minigrid[v2] == self.completed_minigrid[v3]
-        {
-            return Some(self.completed_minigrid[v1]);
-        }
-        None
+    /// Whether `symbol` owns a full meta line (drawn minigrids count only with `draw_counts_for_both`)
+    pub fn has_meta_line(self, symbol: Cell, rules: Ruleset) -> bool {
+        LINES_3.iter().any(|l| {
+            let line = l.map(|i| self.completed_minigrid[i]);
+            line.contains(&symbol)
+                && line
+                    .iter()
+                    .all(|&c| c == symbol || (rules.draw_counts_for_both && c == Cell::Draw))
+        })
     }
-    pub fn is_completed(self) -> Option<Cell> {
-        // If the grid is completed, returns the symbol of the winner
-        let mut results = Vec::new();
-
-        // Check rows
-        results.push(self.check_completed(0, 1, 2));
-        results.push(self.check_completed(3, 4, 5));
-        results.push(self.check_completed(6, 7, 8));
-
-        // Check columns
-        results.push(self.check_completed(0, 3, 6));
-        results.push(self.check_completed(1, 4, 7));
-        results.push(self.check_completed(2, 5, 8));
-
-        // Check diagonals
-        results.push(self.check_completed(0, 4, 8));
-        results.push(self.check_completed(2, 4, 6));
-
-        for r in results {
-            if r.is_some() {
-                let winner_cell: Cell = r.unwrap();
-                return Some(winner_cell);
+    /// Whether the minigrid at `index` can still be played in
+    fn is_playable(&self, index: usize, rules: Ruleset) -> bool {
+        match self.completed_minigrid[index] {
+            Cell::Empty => true,
+            Cell::Cross | Cell::Circle => {
+                rules.play_in_won_minigrids && !self.matrix[index].is_full()
             }
+            Cell::Draw => false,
         }
-        None
+    }
+    pub fn result(self, rules: Ruleset) -> GameResult {
+        let count = |symbol| {
+            self.completed_minigrid
+                .iter()
+                .filter(|&&c| c == symbol)
+                .count() as u32
+        };
+        rules.tiebreak.result(
+            (
+                self.has_meta_line(Cell::Cross, rules),
+                self.has_meta_line(Cell::Circle, rules),
+            ),
+            (0..9).any(|i| self.is_playable(i, rules)),
+            (count(Cell::Cross), count(Cell::Circle)),
+        )
+    }
+
+    /// Minigrids that can still be played in, as (meta_x, meta_y)
+    fn playable_minigrids(&self, rules: Ruleset) -> Vec<(u8, u8)> {
+        (0..9)
+            .filter(|&i| self.is_playable(i, rules))
+            .map(|i| (i as u8 % 3, i as u8 / 3))
+            .collect()
     }
 
     /// Get all valid legal moves based on game state and last move
-    pub fn get_legal_moves(&self, last_move: Option<Coord>) -> Vec<Coord> {
+    pub fn get_legal_moves(&self, last_move: Option<Coord>, rules: Ruleset) -> Vec<Coord> {
         let mut moves = Vec::new();
 
         // Determine which meta grids are playable
@@ -146,37 +234,15 @@ impl Grid {
                 ..
             }) => {
                 let target_meta_idx = (local_x + local_y * 3) as usize;
-                if self.completed_minigrid[target_meta_idx] == Cell::Empty {
+                if self.is_playable(target_meta_idx, rules) {
                     vec![(local_x, local_y)]
                 } else {
-                    // If target meta grid is completed, player can choose any available
-                    self.completed_minigrid
-                        .iter()
-                        .enumerate()
-                        .filter_map(|(i, &cell)| {
-                            if cell == Cell::Empty {
-                                Some((i as u8 % 3, i as u8 / 3))
-                            } else {
-                                None
-                            }
-                        })
-                        .collect()
+                    // If target meta grid is closed, player can choose any available
+                    self.playable_minigrids(rules)
                 }
             }
-            None => {
-                // First move can be anywhere
-                self.completed_minigrid
-                    .iter()
-                    .enumerate()
-                    .filter_map(|(i, &cell)| {
-                        if cell == Cell::Empty {
-                            Some((i as u8 % 3, i as u8 / 3))
-                        } else {
-                            None
-                        }
-                    })
-                    .collect()
-            }
+            // First move can be anywhere
+            None => self.playable_minigrids(rules),
         };
 
         // Check each allowed meta grid for available cells
@@ -205,53 +271,39 @@ impl Grid {
 pub trait Player: Send + Sync {
     /// Run before playing a match
     fn reset(&self);
-    fn select_move(&self, grid: Grid, legal_moves: Vec<Coord>, last_move: Option<Coord>) -> Coord;
+    /// Name and parameters, used in game records
+    fn name(&self) -> String {
+        String::from("Unknown")
+    }
+    /// Pick one of `state.legal_moves()` for `state.side_to_move`
+    fn select_move(&self, state: &GameState) -> Coord;
+    /// Same as `select_move` when playing with a clock; players that don't manage their time can ignore `time`
+    fn select_move_timed(&self, state: &GameState, time: &TimeLeft) -> Coord {
+        let _ = time;
+        self.select_move(state)
+    }
+    /// How good the last selected move is for the player, from -1 (sure loss) to 1 (sure win);
+    /// `None` for players without an evaluation
+    fn evaluation(&self) -> Option<f32> {
+        None
+    }
 }
 
-pub fn play_match<A: Player + Copy, B: Player + Copy>(a: A, b: B) -> MatchStats {
-    a.reset();
-    b.reset();
-
-    let mut grid: Grid = Default::default();
-    let mut last_move: Option<Coord> = None;
-    let mut current_player = Cell::Cross;
-    let mut number_turns: u8 = 0;
-
-    loop {
-        let legal_moves = grid.get_legal_moves(last_move);
-        if legal_moves.len() == 0 {
-            return MatchStats {
-                winner: None,
-                number_turns,
-                final_grid: grid,
-            };
-        }
-
-        let coord = match current_player {
-            Cell::Cross => a.select_move(grid, legal_moves, last_move),
-            Cell::Circle => b.select_move(grid, legal_moves, last_move),
-            _ => panic!("Invalid player state"),
-        };
-
-        grid.set(coord, current_player);
-        grid.update_grid();
-        print_grid(&grid);
-        last_move = Some(coord);
-
-        if let Some(winner_symbol) = grid.is_completed() {
-            return MatchStats {
-                winner: Some(winner_symbol),
-                number_turns,
-                final_grid: grid,
-            };
-        }
-
-        current_player = if current_player == Cell::Cross {
-            Cell::Circle
-        } else {
-            Cell::Cross
-        };
-        number_turns += 1;
+impl<P: Player + ?Sized> Player for Box<P> {
+    fn reset(&self) {
+        (**self).reset()
+    }
+    fn name(&self) -> String {
+        (**self).name()
+    }
+    fn select_move(&self, state: &GameState) -> Coord {
+        (**self).select_move(state)
+    }
+    fn select_move_timed(&self, state: &GameState, time: &TimeLeft) -> Coord {
+        (**self).select_move_timed(state, time)
+    }
+    fn evaluation(&self) -> Option<f32> {
+        (**self).evaluation()
     }
 }
 
@@ -263,6 +315,7 @@ pub fn cell_char(c: Cell) -> char {
         Cell::Empty => '.',
         Cell::Cross => 'X',
         Cell::Circle => 'O',
+        Cell::Draw => '#',
     }
 }
 pub fn print_grid(g: &Grid) {
@@ -295,7 +348,7 @@ pub fn print_grid(g: &Grid) {
     }
 
     out.push_str("\n\n");
-    out.push_str("Completed minigrids (winner per 3x3 block):\n");
+    out.push_str("Completed minigrids (winner per 3x3 block, # if drawn):\n");
     for meta_y in 0..3 {
         for meta_x in 0..3 {
             let idx = (meta_y * 3 + meta_x) as usize;
@@ -0,0 +1,232 @@
+use crate::defs::{Cell, GameResult};
+use crate::tournament::Tournament;
+
+/// Expected score of a player `elo` points stronger than its opponent
+pub fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+/// Elo difference matching an expected score, infinite for a score of 0 or 1
+pub fn elo_from_score(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+/// Wins, draws and losses of a player against another one
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Wdl {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+/// Elo difference with the bounds of its 95% confidence interval
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EloEstimate {
+    pub elo: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl Wdl {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+    /// Average points per game, 1 per win and 1/2 per draw
+    pub fn score(&self) -> f64 {
+        (self.wins as f64 + 0.5 * self.draws as f64) / self.games() as f64
+    }
+    /// Variance of the points of a single game, with a prior of one virtual win, draw and loss
+    /// so that lopsided results (only wins, only draws) don't have a variance of 0
+    fn variance(&self) -> f64 {
+        let (wins, draws, losses) = (
+            self.wins as f64 + 1.0,
+            self.draws as f64 + 1.0,
+            self.losses as f64 + 1.0,
+        );
+        let games = wins + draws + losses;
+        let s = (wins + 0.5 * draws) / games;
+        (wins * (1.0 - s).powi(2) + draws * (0.5 - s).powi(2) + losses * s.powi(2)) / games
+    }
+    /// Elo difference, `None` without games
+    pub fn elo(&self) -> Option<EloEstimate> {
+        if self.games() == 0 {
+            return None;
+        }
+        // Normal approximation of the score, 1.96 standard errors being the 95% interval
+        let margin = 1.96 * (self.variance() / self.games() as f64).sqrt();
+        let score = self.score();
+        Some(EloEstimate {
+            elo: elo_from_score(score),
+            lower: elo_from_score((score - margin).max(0.0)),
+            upper: elo_from_score((score + margin).min(1.0)),
+        })
+    }
+
+    pub fn add(&mut self, result: GameResult, side: Cell) {
+        match result.winner() {
+            Some(winner) if winner == side => self.wins += 1,
+            Some(_) => self.losses += 1,
+            None => self.draws += 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SprtVerdict {
+    /// The Elo difference is at most `elo0`
+    H0,
+    /// The Elo difference is at least `elo1`
+    H1,
+}
+
+/// Sequential probability ratio test of "the Elo difference is `elo0`" against "it is `elo1`",
+/// with `alpha` and `beta` the probabilities of wrongly accepting H1 and H0
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+/// State of a finished or interrupted `Sprt::run`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SprtReport {
+    /// Results of the candidate against the baseline
+    pub wdl: Wdl,
+    pub llr: f64,
+    /// `None` if the game limit was reached first
+    pub verdict: Option<SprtVerdict>,
+}
+
+impl Sprt {
+    pub fn new(elo0: f64, elo1: f64, alpha: f64, beta: f64) -> Sprt {
+        Sprt {
+            elo0,
+            elo1,
+            alpha,
+            beta,
+        }
+    }
+
+    /// (lower, upper) bounds of the log-likelihood ratio, accepting H0 below and H1 above
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+    /// Log-likelihood ratio of H1 over H0, using a normal approximation of the game results
+    pub fn llr(&self, wdl: &Wdl) -> f64 {
+        if wdl.games() == 0 {
+            return 0.0;
+        }
+        let (s0, s1) = (expected_score(self.elo0), expected_score(self.elo1));
+        wdl.games() as f64 * (s1 - s0) * (2.0 * wdl.score() - s0 - s1) / (2.0 * wdl.variance())
+    }
+    pub fn verdict(&self, wdl: &Wdl) -> Option<SprtVerdict> {
+        let llr = self.llr(wdl);
+        let (lower, upper) = self.bounds();
+        if llr <= lower {
+            Some(SprtVerdict::H0)
+        } else if llr >= upper {
+            Some(SprtVerdict::H1)
+        } else {
+            None
+        }
+    }
+
+    /// Run `tournament` again and again, until a verdict on the results of its first player (the candidate)
+    /// against the others is reached or `max_games` were played; `on_batch` is called after each run
+    pub fn run(
+        &self,
+        tournament: &Tournament,
+        max_games: u32,
+        mut on_batch: impl FnMut(&SprtReport),
+    ) -> SprtReport {
+        let mut wdl = Wdl::default();
+        loop {
+            for game in tournament.run() {
+                if game.cross == 0 {
+                    wdl.add(game.stats.result, Cell::Cross);
+                } else if game.circle == 0 {
+                    wdl.add(game.stats.result, Cell::Circle);
+                }
+            }
+            let report = SprtReport {
+                wdl,
+                llr: self.llr(&wdl),
+                verdict: self.verdict(&wdl),
+            };
+            on_batch(&report);
+            // Also stop if the candidate doesn't play in `tournament`, which would never end
+            if report.verdict.is_some() || wdl.games() >= max_games || wdl.games() == 0 {
+                return report;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wdl(wins: u32, draws: u32, losses: u32) -> Wdl {
+        Wdl {
+            wins,
+            draws,
+            losses,
+        }
+    }
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-6, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn elo_estimates() {
+        assert_eq!(wdl(0, 0, 0).elo(), None);
+
+        let estimate = wdl(60, 20, 20).elo().unwrap();
+        assert_close(estimate.elo, 147.190714);
+        assert_close(estimate.lower, 85.984680);
+        assert_close(estimate.upper, 218.579726);
+
+        let estimate = wdl(0, 50, 0).elo().unwrap();
+        assert_eq!(estimate.elo, 0.0);
+        assert_close(estimate.lower, -18.725899);
+        assert_close(estimate.upper, 18.725899);
+
+        // Only wins: the estimate is infinite, but not its lower bound
+        let estimate = wdl(64, 0, 0).elo().unwrap();
+        assert_eq!(estimate.elo, f64::INFINITY);
+        assert_close(estimate.lower, 586.700423);
+        assert_eq!(estimate.upper, f64::INFINITY);
+    }
+
+    #[test]
+    fn bounds_of_the_llr() {
+        let (lower, upper) = Sprt::new(0.0, 5.0, 0.05, 0.05).bounds();
+        assert_close(lower, -2.944439);
+        assert_close(upper, 2.944439);
+        let (lower, upper) = Sprt::new(0.0, 5.0, 0.05, 0.1).bounds();
+        assert_close(lower, (0.1f64 / 0.95).ln());
+        assert_close(upper, (0.9f64 / 0.05).ln());
+    }
+
+    #[test]
+    fn llr_and_verdicts() {
+        let sprt = Sprt::new(0.0, 5.0, 0.05, 0.05);
+        let cases = [
+            (wdl(0, 0, 0), 0.0, None),
+            (wdl(30, 40, 30), -0.017201, None),
+            (wdl(60, 20, 20), 0.875952, None),
+            (wdl(64, 0, 0), 12.590461, Some(SprtVerdict::H1)),
+            (wdl(0, 64, 0), -0.221986, None),
+            (wdl(0, 300, 0), -4.705821, Some(SprtVerdict::H0)),
+            (wdl(0, 0, 64), -12.772953, Some(SprtVerdict::H0)),
+        ];
+        for (results, llr, verdict) in cases {
+            assert_close(sprt.llr(&results), llr);
+            assert_eq!(sprt.verdict(&results), verdict, "{results:?}");
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use crate::bitboard::{is_win, player_index};
+use crate::defs::{Cell, Coord, GameResult};
+use crate::state::GameState;
+use rand::Rng;
+use std::fmt::Debug;
+
+/// A two-player position the search engines can play on
+pub trait Game: Clone + PartialEq + Send + Sync {
+    type Move: Copy + PartialEq + Debug + Send + Sync;
+
+    fn side_to_move(&self) -> Cell;
+    fn result(&self) -> GameResult;
+    fn legal_moves(&self) -> Vec<Self::Move>;
+    /// Uniformly random legal move, `None` if there are no legal moves
+    fn random_legal_move<R: Rng>(&self, rng: &mut R) -> Option<Self::Move>;
+    /// Play `mv` for the side to move; the move must be legal
+    fn play(&mut self, mv: Self::Move);
+}
+
+/// Meta tic-tac-toe played on `size()`×`size()` minigrids of `size()`×`size()` cells
+pub trait MetaGame: Game {
+    fn size(&self) -> usize;
+    /// Index of the minigrid and of the cell inside it, both row-major
+    fn move_indices(&self, mv: Self::Move) -> (usize, usize);
+    /// Whether playing `mv` with `symbol` would give `symbol` a line in the minigrid of `mv`
+    fn completes_line(&self, mv: Self::Move, symbol: Cell) -> bool;
+}
+
+impl Game for GameState {
+    type Move = Coord;
+
+    #[inline]
+    fn side_to_move(&self) -> Cell {
+        self.side_to_move
+    }
+    #[inline]
+    fn result(&self) -> GameResult {
+        GameState::result(self)
+    }
+    fn legal_moves(&self) -> Vec<Coord> {
+        GameState::legal_moves(self)
+    }
+    #[inline]
+    fn random_legal_move<R: Rng>(&self, rng: &mut R) -> Option<Coord> {
+        GameState::random_legal_move(self, rng)
+    }
+    #[inline]
+    fn play(&mut self, mv: Coord) {
+        GameState::play(self, mv)
+    }
+}
+
+impl MetaGame for GameState {
+    fn size(&self) -> usize {
+        3
+    }
+    fn move_indices(&self, mv: Coord) -> (usize, usize) {
+        (mv.meta_index(), mv.cell_index())
+    }
+    fn completes_line(&self, mv: Coord, symbol: Cell) -> bool {
+        let own = self.board.cells[player_index(symbol)][mv.meta_index()];
+        is_win(own | 1 << mv.cell_index())
+    }
+}
@@ -1,4 +1,5 @@
 use crate::defs::*;
+use crate::state::GameState;
 use std::io::{self, Write as IoWrite};
 pub fn input_str() -> String {
     io::stdout().flush().unwrap();
@@ -11,14 +12,13 @@ pub fn input_str() -> String {
     input.trim().to_string()
 }
 
-#[derive(Clone, Copy)]
-pub struct HumanPlayer {
-    symbol: Cell,
-}
+/// Asks for moves on the terminal, for whichever side is to move
+#[derive(Clone, Copy, Default)]
+pub struct HumanPlayer;
 
 impl HumanPlayer {
-    pub fn new(symbol: Cell) -> Self {
-        Self { symbol }
+    pub fn new() -> Self {
+        Self
     }
 }
 
@@ -27,14 +27,20 @@ impl Player for HumanPlayer {
         // Empty function, no reset logic needed
     }
 
-    fn select_move(&self, grid: Grid, legal_moves: Vec<Coord>, _last_move: Option<Coord>) -> Coord {
+    fn name(&self) -> String {
+        String::from("Human")
+    }
+
+    fn select_move(&self, state: &GameState) -> Coord {
+        let grid = state.grid();
+        let legal_moves = state.legal_moves();
         clear_term();
         println!("-----------------------------");
-        println!("- {:?}'s TURN -", self.symbol);
+        println!("- {:?}'s TURN -", state.side_to_move);
         print_grid(&grid);
 
         loop {
-            println!("Enter coordinates (meta_x meta_y x y) between 0-2 separated by spaces:");
+            println!("Enter a move (e.g. e5) or coordinates (meta_x meta_y x y) between 0-2 separated by spaces:");
             print!("> ");
             io::stdout().flush().unwrap();
 
@@ -43,6 +49,13 @@ impl Player for HumanPlayer {
 
             // Parse input coordinates
             let coords = match parts.as_slice() {
+                [notation] => match notation.parse::<Coord>() {
+                    Ok(coord) => Some(coord),
+                    Err(e) => {
+                        println!("{e}");
+                        continue;
+                    }
+                },
                 [mx, my, x, y] => match (mx.parse(), my.parse(), x.parse(), y.parse()) {
                     (Ok(mx), Ok(my), Ok(x), Ok(y)) => Some(Coord {
                         meta_x: mx,
@@ -56,42 +69,24 @@ impl Player for HumanPlayer {
                     }
                 },
                 _ => {
-                    println!("Invalid format: Expected 4 numbers separated by spaces");
+                    println!("Invalid format: Expected a move or 4 numbers separated by spaces");
                     continue;
                 }
             };
 
-            // Validate coordinates
+            // Validate coordinates against the game rules
             if let Some(coord) = coords {
-                // Check all coordinates are within 0-2 range
-                if [coord.meta_x, coord.meta_y, coord.x, coord.y]
-                    .iter()
-                    .any(|&v| v > 2)
-                {
-                    println!("Coordinates must be between 0-2");
-                    continue;
+                let mut next_state = *state;
+                match next_state.try_play(coord) {
+                    Ok(()) => return coord,
+                    Err(e) => println!("Invalid move: {e}"),
                 }
-
-                // Check if move is legal by comparing all fields
-                if legal_moves.iter().any(|c| {
-                    c.meta_x == coord.meta_x
-                        && c.meta_y == coord.meta_y
-                        && c.x == coord.x
-                        && c.y == coord.y
-                }) {
-                    // Create a temporary grid with the move applied
-                    let mut temp_grid = grid;
-                    temp_grid.set(coord, self.symbol);
-                    return coord;
-                } else {
-                    println!("Invalid move: That position is not playable according to game rules");
-                    println!("Legal moves are:");
-                    for m in &legal_moves {
-                        println!(
-                            "- Meta: ({},{}) Local: ({},{})",
-                            m.meta_x, m.meta_y, m.x, m.y
-                        );
-                    }
+                println!("Legal moves are:");
+                for m in &legal_moves {
+                    println!(
+                        "- {m} (Meta: ({},{}) Local: ({},{}))",
+                        m.meta_x, m.meta_y, m.x, m.y
+                    );
                 }
             }
         }
@@ -1,28 +1,368 @@
+pub mod bitboard;
+pub mod clock;
 pub mod defs;
+pub mod elo;
+pub mod game;
 pub mod human;
 pub mod mcts;
+pub mod nboard;
+pub mod notation;
+pub mod openings;
+pub mod perft;
+pub mod record;
+pub mod rules;
+pub mod runner;
+pub mod solver;
+pub mod state;
+pub mod symmetry;
+#[cfg(test)]
+mod testutil;
+pub mod tournament;
+pub mod validate;
 pub mod weighted;
+pub mod zobrist;
 
-use crate::defs::{play_match, Cell};
-use crate::human::HumanPlayer;
+use crate::clock::TimeControl;
+use crate::defs::GameResult;
+use crate::elo::{Sprt, SprtReport, SprtVerdict};
 use crate::mcts::MCTSPlayer;
+use crate::notation::parse_position;
+use crate::record::GameRecord;
+use crate::rules::Ruleset;
+use crate::runner::{Adjudication, EvalRule, MatchRunner};
+use crate::state::GameState;
+use crate::tournament::{Crosstable, PlayerConfig, Tournament, TournamentKind};
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
 
-fn main() {
-    //let human = HumanPlayer::new(Cell::Cross);
-    let ai = MCTSPlayer::new(1.5, 100, Cell::Circle); // Parametri da principiante
-    let ai2 = MCTSPlayer::new(1.5, 100, Cell::Cross);
+#[derive(Parser)]
+#[command(about = "Engines and tools for Ultimate Tic-Tac-Toe")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
 
-    for _ in 0..25 {
-        let stats = play_match(ai2, ai);
+#[derive(Subcommand)]
+enum Command {
+    /// Play MCTS self-play games (default)
+    Selfplay {
+        /// Only print the game records, not every move
+        #[arg(long)]
+        quiet: bool,
+        #[command(flatten)]
+        game: MatchArgs,
+    },
+    /// Count the leaf nodes of the move tree
+    Perft {
+        depth: u32,
+        /// Starting position, in the notation of `notation.rs` (default: empty board)
+        #[arg(long)]
+        position: Option<String>,
+        /// Also print the count below every legal move
+        #[arg(long)]
+        divide: bool,
+        #[command(flatten)]
+        rules: RulesArgs,
+    },
+    /// Check the move generator against the reference perft counts
+    PerftVerify,
+    /// Write an opening suite with every distinct position after a few plies, symmetries excluded
+    Openings {
+        #[arg(long, default_value_t = 2)]
+        plies: u32,
+        /// File to write the suite to (default: standard output)
+        #[arg(long)]
+        output: Option<PathBuf>,
+        #[command(flatten)]
+        rules: RulesArgs,
+    },
+    /// Play a round robin (or gauntlet) between engines and print the crosstable
+    Tournament {
+        /// Engine taking part, as `mcts:<exploration weight>:<simulation steps>[:timed]`; repeat for each player
+        #[arg(long = "player", required = true)]
+        players: Vec<PlayerConfig>,
+        /// Only play the first player against each of the others
+        #[arg(long)]
+        gauntlet: bool,
+        /// Games every pairing plays with each color
+        #[arg(long, default_value_t = 1)]
+        rounds: u32,
+        #[command(flatten)]
+        options: TournamentArgs,
+    },
+    /// Play a candidate against a baseline until an SPRT verdict on their Elo difference
+    Sprt {
+        /// Player to test, in the format of `tournament --player`
+        #[arg(long)]
+        candidate: PlayerConfig,
+        /// Player to compare against
+        #[arg(long)]
+        baseline: PlayerConfig,
+        /// Elo difference of the null hypothesis
+        #[arg(long, default_value_t = 0.0)]
+        elo0: f64,
+        /// Elo difference of the alternative hypothesis
+        #[arg(long, default_value_t = 10.0)]
+        elo1: f64,
+        /// Probability of accepting the alternative hypothesis when the null one is true
+        #[arg(long, default_value_t = 0.05)]
+        alpha: f64,
+        /// Probability of accepting the null hypothesis when the alternative one is true
+        #[arg(long, default_value_t = 0.05)]
+        beta: f64,
+        /// Game pairs (one with each color) played in parallel between two checks of the test
+        #[arg(long, default_value_t = 8)]
+        batch: u32,
+        /// Stop without a verdict after this many games
+        #[arg(long, default_value_t = 20000)]
+        max_games: u32,
+        #[command(flatten)]
+        options: TournamentArgs,
+    },
+}
+
+/// Rule variant of the games
+#[derive(Args)]
+struct RulesArgs {
+    /// Rule variant: a preset name or a comma separated list of flags, see `rules.rs`
+    #[arg(long, default_value_t = Ruleset::STANDARD)]
+    rules: Ruleset,
+}
+
+/// How the games of a match are played
+#[derive(Args)]
+struct MatchArgs {
+    #[command(flatten)]
+    rules: RulesArgs,
+    /// `none`, `<seconds>/move` or `<base seconds>+<increment seconds>`
+    #[arg(long, default_value_t = TimeControl::None)]
+    time_control: TimeControl,
+}
+
+/// Options shared by the commands playing a `Tournament`
+#[derive(Args)]
+struct TournamentArgs {
+    #[command(flatten)]
+    game: MatchArgs,
+    /// Suite file of start positions, each played twice with reversed colors (see `openings.rs`)
+    #[arg(long)]
+    openings: Option<PathBuf>,
+    #[command(flatten)]
+    adjudication: AdjudicationArgs,
+}
+
+impl TournamentArgs {
+    /// Tournament between `players` using these options, with one round and no progress output
+    fn tournament(self, players: Vec<PlayerConfig>, kind: TournamentKind) -> Tournament {
+        let rules = self.game.rules.rules;
+        let mut tournament = Tournament::new(players, kind, rules);
+        tournament.time_control = self.game.time_control;
+        tournament.openings = load_openings(self.openings, rules);
+        tournament.adjudication = self.adjudication.into();
+        tournament
+    }
+}
+
+/// Options to end games early, see `runner::Adjudication`
+#[derive(Args)]
+struct AdjudicationArgs {
+    /// Resign for a player whose evaluation is at most -<score> for <moves> of its moves in a row, as `<score>:<moves>`
+    #[arg(long)]
+    resign: Option<EvalRule>,
+    /// Draw once both evaluations are within <score> of 0 for <moves> moves each in a row, as `<score>:<moves>`
+    #[arg(long)]
+    draw: Option<EvalRule>,
+    /// Finish the game with the exact solver once at most this many empty cells are left
+    #[arg(long)]
+    solve_cells: Option<u32>,
+}
 
-        if stats.winner.is_none() {
-            println!("Pareggio in {} turni", stats.number_turns);
-        } else {
+impl From<AdjudicationArgs> for Adjudication {
+    fn from(args: AdjudicationArgs) -> Adjudication {
+        Adjudication {
+            resign: args.resign,
+            draw: args.draw,
+            solve_cells: args.solve_cells,
+        }
+    }
+}
+
+fn main() {
+    let command = Cli::parse().command.unwrap_or(Command::Selfplay {
+        quiet: false,
+        game: MatchArgs {
+            rules: RulesArgs {
+                rules: Ruleset::STANDARD,
+            },
+            time_control: TimeControl::None,
+        },
+    });
+    match command {
+        Command::Selfplay { quiet, game } => selfplay(game.rules.rules, quiet, game.time_control),
+        Command::Perft {
+            depth,
+            position,
+            divide,
+            rules,
+        } => run_perft(depth, position, divide, rules.rules),
+        Command::PerftVerify => {
+            let failures = perft::verify();
+            for ((position, depth, expected), nodes) in &failures {
+                println!("FAIL {position} depth {depth}: expected {expected}, got {nodes}");
+            }
             println!(
-                "Vittoria di {:?} in {} turni",
-                stats.winner.unwrap(),
-                stats.number_turns
+                "{}/{} reference counts match",
+                perft::REFERENCE.len() - failures.len(),
+                perft::REFERENCE.len()
             );
+            if !failures.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Command::Openings {
+            plies,
+            output,
+            rules,
+        } => {
+            let suite = openings::generate(plies, rules.rules);
+            match output {
+                Some(path) => {
+                    if let Err(e) = openings::save(&path, &suite) {
+                        eprintln!("Can't write {}: {e}", path.display());
+                        std::process::exit(1);
+                    }
+                    println!("{} openings written to {}", suite.len(), path.display());
+                }
+                None => print!("{}", openings::to_string(&suite)),
+            }
+        }
+        Command::Tournament {
+            players,
+            gauntlet,
+            rounds,
+            options,
+        } => {
+            let kind = if gauntlet {
+                TournamentKind::Gauntlet
+            } else {
+                TournamentKind::RoundRobin
+            };
+            let mut tournament = options.tournament(players, kind);
+            tournament.rounds = rounds;
+            tournament.progress = true;
+            let games = tournament.run();
+            let names = tournament.players.iter().map(|p| p.to_string()).collect();
+            println!("\n{}", Crosstable::new(names, &games));
+        }
+        Command::Sprt {
+            candidate,
+            baseline,
+            elo0,
+            elo1,
+            alpha,
+            beta,
+            batch,
+            max_games,
+            options,
+        } => {
+            let mut tournament =
+                options.tournament(vec![candidate, baseline], TournamentKind::Gauntlet);
+            tournament.rounds = batch;
+            let sprt = Sprt::new(elo0, elo1, alpha, beta);
+            let report = sprt.run(&tournament, max_games, |report| {
+                print_sprt_report(&sprt, report)
+            });
+            match report.verdict {
+                Some(SprtVerdict::H0) => {
+                    println!("H0 accepted: the candidate is not {elo1} Elo stronger")
+                }
+                Some(SprtVerdict::H1) => {
+                    println!("H1 accepted: the candidate is at least {elo1} Elo stronger")
+                }
+                None => println!("No verdict after {} games", report.wdl.games()),
+            }
+        }
+    }
+}
+
+/// Openings of the suite file at `path`, or just the empty board without a file
+fn load_openings(path: Option<PathBuf>, rules: Ruleset) -> Vec<GameState> {
+    let Some(path) = path else {
+        return vec![GameState::new(rules)];
+    };
+    match openings::load(&path, rules) {
+        Ok(suite) if !suite.is_empty() => suite,
+        Ok(_) => {
+            eprintln!("{} has no openings", path.display());
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Can't load {}: {e}", path.display());
+            std::process::exit(1);
+        }
+    }
+}
+
+fn print_sprt_report(sprt: &Sprt, report: &SprtReport) {
+    let wdl = report.wdl;
+    let (lower, upper) = sprt.bounds();
+    print!(
+        "Games: {}  W/D/L: {}/{}/{}",
+        wdl.games(),
+        wdl.wins,
+        wdl.draws,
+        wdl.losses
+    );
+    if let Some(elo) = wdl.elo() {
+        print!("  Elo: {:.1} [{:.1}, {:.1}]", elo.elo, elo.lower, elo.upper);
+    }
+    println!("  LLR: {:.2} [{lower:.2}, {upper:.2}]", report.llr);
+}
+
+fn run_perft(depth: u32, position: Option<String>, divide: bool, rules: Ruleset) {
+    let mut state = match position {
+        Some(text) => match parse_position(&text, rules) {
+            Ok(state) => state,
+            Err(e) => {
+                eprintln!("Invalid position: {e}");
+                std::process::exit(1);
+            }
+        },
+        None => GameState::new(rules),
+    };
+
+    if divide {
+        for (coord, nodes) in perft::divide(&state, depth) {
+            println!("{coord}: {nodes}");
+        }
+    }
+    let start = std::time::Instant::now();
+    let nodes = perft::perft(&mut state, depth);
+    let elapsed = start.elapsed();
+    println!(
+        "perft({depth}) = {nodes} in {:.3}s ({:.0} nodes/s)",
+        elapsed.as_secs_f64(),
+        nodes as f64 / elapsed.as_secs_f64()
+    );
+}
+
+fn selfplay(rules: Ruleset, quiet: bool, time_control: TimeControl) {
+    //let human = human::HumanPlayer::new();
+    let ai = MCTSPlayer::time_managed(1.5, 100); // Beginner parameters
+    let ai2 = MCTSPlayer::time_managed(1.5, 100);
+
+    let mut runner = MatchRunner::new(rules);
+    runner.verbose = !quiet;
+    runner.time_control = time_control;
+    for _ in 0..25 {
+        let stats = runner.run(&ai2, &ai);
+
+        match stats.result {
+            GameResult::Win(winner) => {
+                println!("{:?} wins in {} plies", winner, stats.number_turns)
+            }
+            _ => println!("Draw in {} plies", stats.number_turns),
         }
+        println!("{}", GameRecord::from_match(&stats, &ai2, &ai));
     }
 }
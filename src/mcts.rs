@@ -1,278 +1,733 @@
-use crate::defs::{Cell, Coord, Grid, Player};
+use crate::clock::TimeLeft;
+use crate::defs::{Coord, GameResult, Player};
+use crate::game::Game;
+use crate::state::{opponent, GameState};
 use rand::Rng;
 use rayon::prelude::*;
-use std::sync::Arc;
+use std::any::Any;
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
-#[derive(Clone, Copy)]
 pub struct MCTSPlayer {
     exploration_weight: f32,
     simulation_steps: u32,
-    symbol: Cell,
+    /// With a clock, search until the time given by `TimeLeft::move_budget` runs out
+    time_managed: bool,
+    /// Most nodes the search tree can hold; once it's full, iterations go on without expanding it
+    pub max_nodes: usize,
+    /// Stop a search after this long, on top of the other limits
+    pub move_time: Option<Duration>,
+    /// Stop a search once the tree holds this many nodes, counting the ones kept from the previous search
+    pub node_budget: Option<usize>,
+    /// Stop a search as soon as the most visited move can't be overtaken in the iterations left (estimated
+    /// from the speed of the search so far when searching against a deadline); a single legal move is
+    /// played without searching
+    pub early_stop: bool,
+    /// Average score of the move chosen by the last search
+    last_evaluation: Mutex<Option<f32>>,
+    last_search: Mutex<Option<SearchInfo>>,
+    /// `Tree` of the last search, kept to continue from the subtree of the next position
+    tree: Mutex<Option<Box<dyn Any + Send>>>,
 }
 
-use std::sync::atomic::{AtomicU32, Ordering};
+/// What a search did before stopping
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SearchInfo {
+    pub iterations: u32,
+    /// Nodes in the tree at the end of the search, counting the ones kept from the previous search
+    pub nodes: usize,
+    pub time: Duration,
+}
 
-/// Represents a node in the Monte Carlo Tree Search
-struct Node {
-    state: Grid,
-    visits: AtomicU32, // Number of times node was visited
-    score: AtomicU32,  // Accumulated score (stored as f32 bits)
-    children: std::sync::Mutex<Vec<Arc<Node>>>,
-    parent: Option<Arc<Node>>,
-    last_move: Option<Coord>, // Move that led to this node
+/// Results of the simulations that went through a node, counted separately so that threads can update
+/// them concurrently without losing any
+#[derive(Default)]
+struct Stats {
+    wins: AtomicU32,
+    draws: AtomicU32,
+    losses: AtomicU32,
 }
 
-impl Clone for Node {
-    fn clone(&self) -> Self {
-        // Atomic values are manually cloned since they don't implement Clone
+impl Stats {
+    /// Count a simulation with `reward` 1 (win), 0 (draw) or -1 (loss)
+    fn add(&self, reward: i8) {
+        let counter = match reward {
+            1 => &self.wins,
+            0 => &self.draws,
+            _ => &self.losses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+    /// Number of times the node was visited
+    fn visits(&self) -> u32 {
+        self.wins.load(Ordering::Relaxed)
+            + self.draws.load(Ordering::Relaxed)
+            + self.losses.load(Ordering::Relaxed)
+    }
+    /// Average reward, from -1 (every simulation lost) to 1 (every simulation won), 0 without visits
+    fn mean(&self) -> f32 {
+        let wins = self.wins.load(Ordering::Relaxed);
+        let losses = self.losses.load(Ordering::Relaxed);
+        let visits = wins + self.draws.load(Ordering::Relaxed) + losses;
+        if visits == 0 {
+            return 0.0;
+        }
+        (wins as f32 - losses as f32) / visits as f32
+    }
+}
+
+/// Represents a node in the Monte Carlo Tree Search; its position is found by playing the moves of the
+/// nodes above it from the root, which tells the side to move and where it must play
+struct Node<M> {
+    last_move: Option<M>, // Move that led to this node
+    /// From the point of view of the player who made `last_move`
+    stats: Stats,
+    /// Set by the thread that expands the node, so that only one does
+    expanding: AtomicBool,
+    /// Indices of the children in the arena, set once they are all stored
+    children: OnceLock<Range<u32>>,
+}
+
+impl<M: Copy> Node<M> {
+    fn new(last_move: Option<M>) -> Node<M> {
         Node {
-            state: self.state,
-            visits: AtomicU32::new(self.visits.load(Ordering::Relaxed)),
-            score: AtomicU32::new(self.score.load(Ordering::Relaxed)),
-            children: std::sync::Mutex::new(self.children.lock().unwrap().clone()),
-            parent: self.parent.clone(),
-            last_move: self.last_move,
+            last_move,
+            stats: Stats::default(),
+            expanding: AtomicBool::new(false),
+            children: OnceLock::new(),
+        }
+    }
+    /// Same move and statistics, without the children
+    fn copy(&self) -> Node<M> {
+        let node = Node::new(self.last_move);
+        for (to, from) in [
+            (&node.stats.wins, &self.stats.wins),
+            (&node.stats.draws, &self.stats.draws),
+            (&node.stats.losses, &self.stats.losses),
+        ] {
+            to.store(from.load(Ordering::Relaxed), Ordering::Relaxed);
+        }
+        node
+    }
+}
+
+/// Nodes stored by index in blocks of `CHUNK_SIZE` allocated when first needed, so that the children of a
+/// node get contiguous indices and threads can add nodes without locking the tree
+struct Arena<M> {
+    chunks: Box<[OnceLock<Chunk<M>>]>,
+    /// Number of indices handed out
+    len: AtomicUsize,
+    capacity: usize,
+}
+
+const CHUNK_SIZE: usize = 1 << 12;
+type Chunk<M> = Box<[OnceLock<Node<M>>]>;
+
+impl<M: Copy> Arena<M> {
+    /// Arena holding `root` at index 0
+    fn new(root: Node<M>, capacity: usize) -> Arena<M> {
+        let capacity = capacity.clamp(1, u32::MAX as usize);
+        let arena = Arena {
+            chunks: (0..capacity.div_ceil(CHUNK_SIZE))
+                .map(|_| OnceLock::new())
+                .collect(),
+            len: AtomicUsize::new(1),
+            capacity,
+        };
+        arena.set(0, root);
+        arena
+    }
+
+    /// Reserve `count` contiguous indices, `None` if the arena is full
+    fn alloc(&self, count: usize) -> Option<Range<u32>> {
+        let start = self
+            .len
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |len| {
+                (len + count <= self.capacity).then_some(len + count)
+            })
+            .ok()?;
+        Some(start as u32..(start + count) as u32)
+    }
+    /// Store `node` at an index returned by `alloc`
+    fn set(&self, index: u32, node: Node<M>) {
+        let index = index as usize;
+        let chunk = self.chunks[index / CHUNK_SIZE]
+            .get_or_init(|| (0..CHUNK_SIZE).map(|_| OnceLock::new()).collect());
+        assert!(
+            chunk[index % CHUNK_SIZE].set(node).is_ok(),
+            "node {index} stored twice"
+        );
+    }
+    /// Number of nodes stored
+    fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+    fn get(&self, index: u32) -> &Node<M> {
+        let index = index as usize;
+        self.chunks[index / CHUNK_SIZE].get().unwrap()[index % CHUNK_SIZE]
+            .get()
+            .unwrap()
+    }
+
+    /// New arena with the subtree of the node at `root`, as much of it as fits in `capacity` nodes
+    fn subtree(&self, root: u32, capacity: usize) -> Arena<M> {
+        let arena = Arena::new(self.get(root).copy(), capacity);
+        // Nodes are copied level by level, so that a full arena keeps the top of the tree
+        let mut queue = VecDeque::from([(root, 0)]);
+        while let Some((from, to)) = queue.pop_front() {
+            let Some(children) = self.get(from).children.get() else {
+                continue;
+            };
+            let Some(copies) = arena.alloc(children.len()) else {
+                break;
+            };
+            for (child, copy) in children.clone().zip(copies.clone()) {
+                arena.set(copy, self.get(child).copy());
+                queue.push_back((child, copy));
+            }
+            let node = arena.get(to);
+            node.expanding.store(true, Ordering::Relaxed);
+            node.children.set(copies).unwrap();
         }
+        arena
     }
 }
 
-impl Node {
-    // These methods are not used, but keeping them commented in case they're needed later
-    // fn get_visits(&self) -> u32 {
-    //     self.visits.load(Ordering::Relaxed)
-    // }
-    //
-    // fn get_score(&self) -> f32 {
-    //     f32::from_bits(self.score.load(Ordering::Relaxed))
-    // }
+/// Search tree with the position of its root
+struct Tree<G: Game> {
+    root: G,
+    arena: Arena<G::Move>,
+}
+
+impl<G: Game> Tree<G> {
+    /// Index of the node of `state`, looking up to two plies (our move and the reply) below the root
+    fn find(&self, state: &G) -> Option<u32> {
+        let mut level = vec![(0, self.root.clone())];
+        for depth in 0..=2 {
+            if let Some(&(index, _)) = level.iter().find(|(_, s)| s == state) {
+                return Some(index);
+            }
+            if depth == 2 {
+                break;
+            }
+            level = level
+                .iter()
+                .filter_map(|(index, s)| Some((s, self.arena.get(*index).children.get()?)))
+                .flat_map(|(s, children)| {
+                    children.clone().map(move |child| {
+                        let mut s = s.clone();
+                        s.play(self.arena.get(child).last_move.unwrap());
+                        (child, s)
+                    })
+                })
+                .collect();
+        }
+        None
+    }
 }
 
 impl MCTSPlayer {
     /// Creates a new MCTS player with specified parameters:
     /// - `exploration_weight`: Balance between exploration/exploitation (typically 1.0-2.0)
     /// - `simulation_steps`: Number of MCTS iterations per move
-    /// - `symbol`: Which player symbol (Cross/Circle) this AI represents
-    pub fn new(exploration_weight: f32, simulation_steps: u32, symbol: Cell) -> Self {
+    ///
+    /// The player plays whichever side is to move in the positions it's given.
+    pub fn new(exploration_weight: f32, simulation_steps: u32) -> Self {
         Self {
             exploration_weight,
             simulation_steps,
-            symbol,
+            time_managed: false,
+            // Nodes take about 40 bytes, so at most 160 MB
+            max_nodes: 1 << 22,
+            move_time: None,
+            node_budget: None,
+            early_stop: false,
+            last_evaluation: Mutex::new(None),
+            last_search: Mutex::new(None),
+            tree: Mutex::new(None),
+        }
+    }
+    /// Same as `new`, but with a time control the player searches for as long as its clock allows;
+    /// `simulation_steps` is only used for moves without a clock
+    pub fn time_managed(exploration_weight: f32, simulation_steps: u32) -> Self {
+        Self {
+            time_managed: true,
+            ..Self::new(exploration_weight, simulation_steps)
         }
     }
 
     /// Calculate Upper Confidence Bound (UCB) for node selection
-    fn ucb(&self, node: &Node) -> f32 {
-        let visits = node.visits.load(Ordering::Relaxed);
+    fn ucb<M>(&self, node: &Node<M>, parent_visits: u32) -> f32 {
+        let visits = node.stats.visits();
         if visits == 0 {
             return f32::INFINITY; // Prioritize unvisited nodes
         }
 
-        let score = f32::from_bits(node.score.load(Ordering::Relaxed));
-        let parent_visits = node.parent.as_ref().unwrap().visits.load(Ordering::Relaxed) as f32;
-
         // UCB formula: exploitation term + exploration term
-        (score / visits as f32)
-            + self.exploration_weight * (parent_visits.ln() / visits as f32).sqrt()
+        node.stats.mean()
+            + self.exploration_weight * ((parent_visits as f32).ln() / visits as f32).sqrt()
     }
 
-    /// Select child node with highest UCB score using parallel iteration
-    fn select_best_child(&self, node: &Node) -> Arc<Node> {
-        let children = node.children.lock().unwrap();
-        if children.is_empty() {
-            return Arc::new(node.clone()); // Return self if no children
-        }
-
-        // Collect children to avoid holding the lock during UCB computation
-        let children_vec: Vec<Arc<Node>> = children.iter().cloned().collect();
-        drop(children); // Release the lock early
-        
-        children_vec
-            .into_par_iter()
-            .max_by(|a, b| {
-                self.ucb(a)
-                    .partial_cmp(&self.ucb(b))
-                    .unwrap_or(std::cmp::Ordering::Equal)
-            })
-            .unwrap_or_else(|| Arc::new(node.clone())) // Fallback to current node
+    /// Child with the highest UCB score, `None` if the node isn't expanded
+    fn select_best_child<M: Copy>(&self, arena: &Arena<M>, index: u32) -> Option<u32> {
+        let node = arena.get(index);
+        let parent_visits = node.stats.visits();
+        node.children.get()?.clone().max_by(|&a, &b| {
+            self.ucb(arena.get(a), parent_visits)
+                .partial_cmp(&self.ucb(arena.get(b), parent_visits))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
     }
 
-    /// Run Monte Carlo simulation from current state to terminal game state
-    fn simulate(&self, state: &Grid) -> f32 {
+    /// Play random moves from `state`, which the rollout continues as is (same side to move and forced
+    /// minigrid), until the game is over
+    fn simulate<G: Game>(state: &G) -> GameResult {
         let mut rng = rand::thread_rng();
-        let mut sim_state = *state;
-        let mut current_player = self.symbol;
-        let mut last_move: Option<Coord> = None;
+        let mut sim_state = state.clone();
 
         // Play out random moves until game conclusion
         loop {
-            // Check if the game is completed
-            if let Some(winner) = sim_state.is_completed() {
-                return match winner {
-                    w if w == self.symbol => 1.0,
-                    w if w != Cell::Empty => -1.0,
-                    _ => 0.0,
-                };
+            let result = sim_state.result();
+            if result.is_over() {
+                return result;
             }
 
-            // Get legal moves
-            let legal_moves = sim_state.get_legal_moves(last_move);
-            if legal_moves.is_empty() {
-                return 0.0; // Draw if no moves available
+            // Select random move from available options
+            let random_move = sim_state.random_legal_move(&mut rng).unwrap();
+            sim_state.play(random_move);
+        }
+    }
+
+    /// Backpropagate simulation results through the nodes of `path` (from the root), `reward` being the one
+    /// of the player who made the move leading to the last node; it's negated at every ply since
+    /// consecutive nodes are scored for opposite players
+    fn backpropagate<M: Copy>(arena: &Arena<M>, path: &[u32], mut reward: i8) {
+        for &index in path.iter().rev() {
+            arena.get(index).stats.add(reward);
+            reward = -reward;
+        }
+    }
+
+    /// Best move for the side to move of `state`, on any board implementing `Game`
+    pub fn search<G: Game + 'static>(&self, state: &G) -> G::Move {
+        self.search_until(state, None)
+    }
+    /// Same as `search`, iterating until `deadline` instead of `simulation_steps` times if there is one;
+    /// `move_time`, `node_budget` and `early_stop` can end the search sooner
+    pub fn search_until<G: Game + 'static>(&self, state: &G, deadline: Option<Instant>) -> G::Move {
+        let start = Instant::now();
+        if self.early_stop {
+            if let [only_move] = state.legal_moves()[..] {
+                *self.last_search.lock().unwrap() = Some(SearchInfo {
+                    iterations: 0,
+                    nodes: 0,
+                    time: start.elapsed(),
+                });
+                *self.last_evaluation.lock().unwrap() = None;
+                return only_move;
             }
+        }
+        let arena = self
+            .take_subtree(state)
+            .unwrap_or_else(|| Arena::new(Node::new(None), self.max_nodes));
+
+        let max_iterations = match deadline {
+            None => Some(self.simulation_steps),
+            Some(_) => None,
+        };
+        let deadline = match (deadline, self.move_time.and_then(|t| start.checked_add(t))) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+
+        // Parallel MCTS iterations, until one of the threads finds a reason to stop
+        let iterations = AtomicU32::new(0);
+        let stop = AtomicBool::new(false);
+        (0..rayon::current_num_threads())
+            .into_par_iter()
+            .for_each(|_| {
+                while !stop.load(Ordering::Relaxed) {
+                    let claimed =
+                        iterations.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |done| {
+                            max_iterations
+                                .is_none_or(|max| done < max)
+                                .then_some(done + 1)
+                        });
+                    let Ok(done) = claimed else {
+                        break;
+                    };
+                    self.iterate(&arena, state);
+
+                    let now = Instant::now();
+                    let out_of_time = deadline.is_some_and(|deadline| now >= deadline);
+                    let out_of_nodes = self.node_budget.is_some_and(|budget| arena.len() >= budget);
+                    // Checking every iteration would slow the search down for little gain
+                    let decided = self.early_stop && done % 16 == 0 && {
+                        let by_count = max_iterations.map(|max| max - done - 1);
+                        let by_time = deadline.map(|deadline| {
+                            let rate = (done + 1) as f64 / (now - start).as_secs_f64();
+                            (rate * (deadline - now).as_secs_f64()) as u32
+                        });
+                        // The other threads may each be in the middle of an iteration they already claimed
+                        let in_flight = rayon::current_num_threads() as u32 - 1;
+                        let remaining = by_count.into_iter().chain(by_time).min().unwrap_or(0);
+                        Self::cannot_be_overtaken(&arena, remaining.saturating_add(in_flight))
+                    };
+                    if out_of_time || out_of_nodes || decided {
+                        stop.store(true, Ordering::Relaxed);
+                    }
+                }
+            });
+        *self.last_search.lock().unwrap() = Some(SearchInfo {
+            iterations: iterations.load(Ordering::Relaxed),
+            nodes: arena.len(),
+            time: start.elapsed(),
+        });
 
-            // Select random move from available options
-            let random_move = legal_moves[rng.gen_range(0..legal_moves.len())];
-            sim_state.set(random_move, current_player);
-            sim_state.update_grid();
-
-            // Update last move
-            last_move = Some(random_move);
-
-            // Switch players for next turn
-            current_player = match current_player {
-                Cell::Cross => Cell::Circle,
-                Cell::Circle => Cell::Cross,
-                _ => panic!("Invalid player"),
-            };
+        // Select the move with the highest number of visits from the root's children
+        let best_child = arena
+            .get(0)
+            .children
+            .get()
+            .and_then(|children| {
+                children
+                    .clone()
+                    .max_by_key(|&i| arena.get(i).stats.visits())
+            })
+            .map(|i| arena.get(i));
+
+        *self.last_evaluation.lock().unwrap() = best_child
+            .filter(|child| child.stats.visits() > 0)
+            .map(|child| child.stats.mean());
+        // Fallback: pick the first legal move if no child found
+        let best_move = best_child
+            .and_then(|child| child.last_move)
+            .unwrap_or_else(|| state.legal_moves()[0]);
+
+        *self.tree.lock().unwrap() = Some(Box::new(Tree {
+            root: state.clone(),
+            arena,
+        }));
+        best_move
+    }
+
+    /// Whether the most visited child of the root stays so after `remaining` more iterations, whatever
+    /// children they go through
+    fn cannot_be_overtaken<M: Copy>(arena: &Arena<M>, remaining: u32) -> bool {
+        let Some(children) = arena.get(0).children.get() else {
+            return false;
+        };
+        let (mut best, mut second) = (0, 0);
+        for visits in children.clone().map(|i| arena.get(i).stats.visits()) {
+            if visits > best {
+                second = best;
+                best = visits;
+            } else if visits > second {
+                second = visits;
+            }
         }
+        best - second > remaining
     }
 
-    /// Backpropagate simulation results through the tree
-    fn backpropagate(node: &Arc<Node>, result: f32) {
-        let mut current = node.clone();
-        loop {
-            // Update the current node
-            current.visits.fetch_add(1, Ordering::Relaxed);
-            current.score.fetch_add(result.to_bits(), Ordering::Relaxed);
-            
-            // Move to parent
-            if let Some(parent) = &current.parent {
-                current = parent.clone();
-            } else {
-                break;
+    /// What the last search did, `None` before the first one
+    pub fn last_search(&self) -> Option<SearchInfo> {
+        *self.last_search.lock().unwrap()
+    }
+
+    /// Subtree of the last search for `state`, if it's the position of the last search or one or two plies
+    /// after it; the rest of the tree is dropped
+    fn take_subtree<G: Game + 'static>(&self, state: &G) -> Option<Arena<G::Move>> {
+        let tree = self
+            .tree
+            .lock()
+            .unwrap()
+            .take()?
+            .downcast::<Tree<G>>()
+            .ok()?;
+        let index = tree.find(state)?;
+        Some(tree.arena.subtree(index, self.max_nodes))
+    }
+
+    /// One MCTS iteration from the root position `root`: selection, expansion, simulation and
+    /// backpropagation
+    fn iterate<G: Game>(&self, arena: &Arena<G::Move>, root: &G) {
+        let mut state = root.clone();
+        let mut path = vec![0];
+
+        // Selection phase - traverse tree using UCB until leaf node
+        while let Some(best_child) = self.select_best_child(arena, *path.last().unwrap()) {
+            state.play(arena.get(best_child).last_move.unwrap());
+            path.push(best_child);
+        }
+
+        // Expand nodes of games that aren't over yet on their first visit; other threads reaching the
+        // node before its children are stored simulate from the node itself
+        let leaf = arena.get(*path.last().unwrap());
+        if !state.result().is_over() && !leaf.expanding.swap(true, Ordering::Relaxed) {
+            let legal_moves = state.legal_moves();
+            // Without room for the children, the node stays a leaf
+            if let Some(children) = arena.alloc(legal_moves.len()) {
+                for (index, m) in children.clone().zip(legal_moves) {
+                    arena.set(index, Node::new(Some(m)));
+                }
+                leaf.children.set(children.clone()).unwrap();
+
+                // Pick a random child to simulate from
+                let index = rand::thread_rng().gen_range(children);
+                state.play(arena.get(index).last_move.unwrap());
+                path.push(index);
             }
         }
+
+        // Simulation phase - play out random game from the selected state
+        let mover = opponent(state.side_to_move());
+        let result = Self::simulate(&state);
+
+        // Backpropagation phase - update tree statistics
+        let reward = match result.winner() {
+            Some(winner) if winner == mover => 1,
+            Some(_) => -1,
+            None => 0,
+        };
+        Self::backpropagate(arena, &path, reward);
     }
 }
 
 impl Player for MCTSPlayer {
     fn reset(&self) {
-        // No state needs to be reset between matches
+        *self.last_evaluation.lock().unwrap() = None;
+        *self.last_search.lock().unwrap() = None;
+        *self.tree.lock().unwrap() = None;
+    }
+
+    fn name(&self) -> String {
+        format!(
+            "MCTS(exploration_weight={}, simulation_steps={}{})",
+            self.exploration_weight,
+            self.simulation_steps,
+            if self.time_managed {
+                ", time_managed"
+            } else {
+                ""
+            }
+        )
     }
 
     /// Select best move using MCTS algorithm
-    fn select_move(
-        &self,
-        grid: Grid,
-        initial_legal_moves: Vec<Coord>,
-        last_move: Option<Coord>,
-    ) -> Coord {
-        let root = Arc::new(Node {
-            state: grid,
-            visits: AtomicU32::new(0),
-            score: AtomicU32::new(0.0f32.to_bits()),
-            children: std::sync::Mutex::new(Vec::new()),
-            parent: None,
-            last_move: None,
-        });
+    fn select_move(&self, state: &GameState) -> Coord {
+        self.search(state)
+    }
 
-        // Always expand the root node with all legal moves first
-        {
-            let mut root_children = root.children.lock().unwrap();
-            for m in &initial_legal_moves {
-                let mut new_state = grid;
-                new_state.set(*m, self.symbol);
-                new_state.update_grid();
-
-                let child_node = Arc::new(Node {
-                    state: new_state,
-                    visits: AtomicU32::new(0),
-                    score: AtomicU32::new(0.0f32.to_bits()),
-                    children: std::sync::Mutex::new(Vec::new()),
-                    parent: Some(root.clone()),
-                    last_move: Some(*m),
-                });
-                root_children.push(child_node);
-            }
+    fn select_move_timed(&self, state: &GameState, time: &TimeLeft) -> Coord {
+        let deadline = Instant::now().checked_add(time.move_budget());
+        if self.time_managed && deadline.is_some() {
+            self.search_until(state, deadline)
+        } else {
+            self.search(state)
         }
+    }
 
-        // Parallel MCTS iterations
-        (0..self.simulation_steps).into_par_iter().for_each(|_| {
-            let mut current_node = root.clone();
+    fn evaluation(&self) -> Option<f32> {
+        *self.last_evaluation.lock().unwrap()
+    }
+}
 
-            // Selection phase - traverse tree using UCB until leaf node
-            loop {
-                // Check if node has children
-                let children_guard = current_node.children.lock().unwrap();
-                if children_guard.is_empty() {
-                    break;
-                }
-                // Clone the best child before dropping the lock
-                let best_child = self.select_best_child(&current_node);
-                drop(children_guard);
-                current_node = best_child;
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notation::parse_position;
+    use crate::rules::Ruleset;
+    use crate::testutil::{random_game, O_TO_BLOCK, X_TO_WIN};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn mean_matches_simulated_results() {
+        let stats = Stats::default();
+        assert_eq!(stats.mean(), 0.0);
+        for reward in [1, 1, 1, 0, -1, 1, 0, -1] {
+            stats.add(reward);
+        }
+        assert_eq!(stats.visits(), 8);
+        assert_eq!(stats.mean(), 2.0 / 8.0);
 
-            // Always use get_legal_moves to ensure we're following game rules
-            let legal_moves = current_node.state.get_legal_moves(current_node.last_move);
-
-            // If there are legal moves and the node hasn't been expanded yet, expand
-            if current_node.visits.load(Ordering::Relaxed) == 0 && !legal_moves.is_empty() {
-                // Create child nodes for all legal moves
-                let mut children = current_node.children.lock().unwrap();
-                // Check again in case another thread already expanded this node
-                if children.is_empty() {
-                    for m in &legal_moves {
-                        let mut new_state = current_node.state;
-                        new_state.set(*m, self.symbol);
-                        new_state.update_grid();
-
-                        let child_node = Arc::new(Node {
-                            state: new_state,
-                            visits: AtomicU32::new(0),
-                            score: AtomicU32::new(0.0f32.to_bits()),
-                            children: std::sync::Mutex::new(Vec::new()),
-                            parent: Some(current_node.clone()),
-                            last_move: Some(*m),
-                        });
-                        children.push(child_node);
-                    }
-                }
-            }
+        let losses = Stats::default();
+        losses.add(-1);
+        losses.add(-1);
+        assert_eq!(losses.mean(), -1.0);
+    }
 
-            // Select node to simulate from
-            let node_to_simulate = {
-                let children = current_node.children.lock().unwrap();
-                if children.is_empty() {
-                    current_node.clone()
-                } else {
-                    // Pick a random child to simulate from
-                    let mut rng = rand::thread_rng();
-                    let index = rng.gen_range(0..children.len());
-                    children[index].clone()
-                }
-            };
+    #[test]
+    fn concurrent_results_are_all_counted() {
+        let stats = Stats::default();
+        (0..30_000)
+            .into_par_iter()
+            .for_each(|i| stats.add([1, 0, -1, 1][i % 4]));
+        assert_eq!(stats.visits(), 30_000);
+        assert_eq!(stats.mean(), 7_500.0 / 30_000.0);
+    }
 
-            // Simulation phase - play out random game from the selected state
-            let result = self.simulate(&node_to_simulate.state);
+    #[test]
+    fn backpropagation_alternates_perspective() {
+        let arena = Arena::new(Node::new(None), 16);
+        let child = arena.alloc(1).unwrap().start;
+        arena.set(child, Node::new(Some(Coord::from_indices(4, 4))));
+        let grandchild = arena.alloc(1).unwrap().start;
+        arena.set(grandchild, Node::new(Some(Coord::from_indices(4, 0))));
+
+        // Rewards of the player who made the move leading to `grandchild`
+        let path = [0, child, grandchild];
+        for reward in [-1, -1, 0, 1] {
+            MCTSPlayer::backpropagate(&arena, &path, reward);
+        }
+        assert_eq!(arena.get(grandchild).stats.mean(), -1.0 / 4.0);
+        assert_eq!(arena.get(child).stats.mean(), 1.0 / 4.0);
+        assert_eq!(arena.get(0).stats.mean(), -1.0 / 4.0);
+        assert_eq!(arena.get(0).stats.visits(), 4);
+    }
 
-            // Backpropagation phase - update tree statistics
-            Self::backpropagate(&node_to_simulate, result);
-        });
+    #[test]
+    fn arena_stops_growing_at_its_capacity() {
+        let arena: Arena<Coord> = Arena::new(Node::new(None), CHUNK_SIZE + 10);
+        assert_eq!(arena.alloc(CHUNK_SIZE), Some(1..CHUNK_SIZE as u32 + 1));
+        assert_eq!(arena.alloc(10), None);
+        assert_eq!(
+            arena.alloc(9),
+            Some(CHUNK_SIZE as u32 + 1..CHUNK_SIZE as u32 + 10)
+        );
+        assert_eq!(arena.alloc(1), None);
+    }
 
-        // Select the move with the highest number of visits from the root's children
-        let children = root.children.lock().unwrap();
-        let best_child = children
-            .iter()
-            .max_by_key(|child| child.visits.load(Ordering::Relaxed));
-
-        if let Some(child) = best_child {
-            if let Some(mv) = child.last_move {
-                return mv;
-            }
-        }
+    #[test]
+    fn search_works_with_a_full_arena() {
+        let state = parse_position(X_TO_WIN, Ruleset::STANDARD).unwrap();
+        let mut player = MCTSPlayer::new(1.4, 2000);
+        // Only room for the root and its children
+        player.max_nodes = 1 + state.legal_moves().len();
+        assert_eq!(player.select_move(&state), Coord::from_indices(2, 2));
+    }
 
-        // Fallback: pick the first legal move if no child found
-        *initial_legal_moves.first().unwrap()
+    #[test]
+    fn subtree_is_reused_after_move_and_reply() {
+        let mut state = GameState::new(Ruleset::STANDARD);
+        let player = MCTSPlayer::new(1.4, 2000);
+        state.play(player.search(&state));
+        let reply = state.legal_moves()[0];
+        state.play(reply);
+
+        // Visits of the node of `state` and of its children in the tree of the last search
+        let visits = |arena: &Arena<Coord>, index: u32| {
+            let node = arena.get(index);
+            let children = node.children.get().cloned().unwrap_or_default();
+            let children: Vec<u32> = children.map(|i| arena.get(i).stats.visits()).collect();
+            (node.stats.visits(), children)
+        };
+        let expected = {
+            let tree = player.tree.lock().unwrap();
+            let tree = tree.as_ref().unwrap().downcast_ref::<Tree<GameState>>();
+            let tree = tree.unwrap();
+            visits(&tree.arena, tree.find(&state).unwrap())
+        };
+        assert!(expected.0 > 0);
+        let subtree = player.take_subtree(&state).unwrap();
+        assert_eq!(visits(&subtree, 0), expected);
+    }
+
+    #[test]
+    fn tree_is_dropped_for_unrelated_positions_and_on_reset() {
+        let state = GameState::new(Ruleset::STANDARD);
+        let player = MCTSPlayer::new(1.4, 500);
+        player.search(&state);
+        let unrelated = parse_position(X_TO_WIN, Ruleset::STANDARD).unwrap();
+        assert!(player.take_subtree(&unrelated).is_none());
+        assert!(player.tree.lock().unwrap().is_none());
+
+        player.search(&state);
+        player.reset();
+        assert!(player.tree.lock().unwrap().is_none());
+        assert!(player.take_subtree(&state).is_none());
+    }
+
+    #[test]
+    fn reports_iterations() {
+        let player = MCTSPlayer::new(1.4, 300);
+        assert_eq!(player.last_search(), None);
+        player.search(&GameState::new(Ruleset::STANDARD));
+        let info = player.last_search().unwrap();
+        assert_eq!(info.iterations, 300);
+        assert!(info.nodes > 81);
+        player.reset();
+        assert_eq!(player.last_search(), None);
+    }
+
+    #[test]
+    fn stops_at_node_budget() {
+        let mut player = MCTSPlayer::new(1.4, 100_000);
+        player.node_budget = Some(1000);
+        player.search(&GameState::new(Ruleset::STANDARD));
+        let info = player.last_search().unwrap();
+        assert!(info.iterations < 1000);
+        // The last expansion can go over the budget by a node's children
+        assert!((1000..1000 + 81).contains(&info.nodes));
+    }
+
+    #[test]
+    fn stops_at_move_time() {
+        let mut player = MCTSPlayer::new(1.4, u32::MAX);
+        player.move_time = Some(Duration::from_millis(50));
+        player.search(&GameState::new(Ruleset::STANDARD));
+        let info = player.last_search().unwrap();
+        assert!(info.time >= Duration::from_millis(50));
+        assert!(info.time < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn stops_early_once_the_best_move_is_decided() {
+        let state = parse_position(X_TO_WIN, Ruleset::STANDARD).unwrap();
+        let mut player = MCTSPlayer::new(1.4, 50_000);
+        player.early_stop = true;
+        assert_eq!(player.select_move(&state), Coord::from_indices(2, 2));
+        assert!(player.last_search().unwrap().iterations < 50_000);
+    }
+
+    #[test]
+    fn stops_at_once_with_a_single_legal_move() {
+        let mut rng = StdRng::seed_from_u64(25);
+        let state = std::iter::repeat_with(|| random_game(Ruleset::STANDARD, &mut rng))
+            .flatten()
+            .find(|state| !state.result().is_over() && state.legal_moves().len() == 1)
+            .unwrap();
+        let mut player = MCTSPlayer::new(1.4, 50_000);
+        player.early_stop = true;
+        assert_eq!(player.select_move(&state), state.legal_moves()[0]);
+        assert_eq!(player.last_search().unwrap().iterations, 0);
+        assert_eq!(player.evaluation(), None);
+    }
+
+    #[test]
+    fn evaluation_is_cleared_by_a_search_without_visits() {
+        let mut player = MCTSPlayer::new(1.4, 2000);
+        player.select_move(&parse_position(X_TO_WIN, Ruleset::STANDARD).unwrap());
+        assert!(player.evaluation().is_some());
+        player.simulation_steps = 0;
+        player.select_move(&parse_position(O_TO_BLOCK, Ruleset::STANDARD).unwrap());
+        assert_eq!(player.evaluation(), None);
+    }
+
+    #[test]
+    fn finds_one_move_win() {
+        let state = parse_position(X_TO_WIN, Ruleset::STANDARD).unwrap();
+        let player = MCTSPlayer::new(1.4, 20_000);
+        assert_eq!(player.select_move(&state), Coord::from_indices(2, 2));
+    }
+
+    #[test]
+    fn blocks_one_move_loss() {
+        let state = parse_position(O_TO_BLOCK, Ruleset::STANDARD).unwrap();
+        let player = MCTSPlayer::new(1.4, 20_000);
+        assert_eq!(player.select_move(&state), Coord::from_indices(2, 2));
     }
 }
@@ -0,0 +1,309 @@
+//! Meta tic-tac-toe on a board of any size: `size`×`size` minigrids of `size`×`size` cells, where `k` in a row
+//! wins a minigrid and `k` won minigrids in a row win the game. Move generation and `Ruleset` work like in
+//! the 3×3 game, which keeps its own faster bitboard implementation in `crate::state`.
+
+use crate::defs::{cell_char, Cell, GameResult};
+use crate::game::{Game, MetaGame};
+use crate::rules::Ruleset;
+use crate::state::opponent;
+use rand::Rng;
+use std::fmt;
+
+/// Cell of an `NGameState`, as the index of its minigrid and the index of the cell inside it (both row-major)
+#[derive(Clone, Copy, Debug, PartialEq, Hash, Eq)]
+pub struct NCoord {
+    pub meta: u16,
+    pub cell: u16,
+}
+
+#[derive(Clone, Debug, PartialEq, Hash, Eq)]
+pub struct NGameState {
+    pub size: usize,
+    /// Length of a winning line, both in minigrids and on the meta board
+    pub k: usize,
+    /// Cells of all the minigrids, the one at `cell` in minigrid `meta` being `cells[meta * size² + cell]`
+    pub cells: Vec<Cell>,
+    /// Status of each minigrid, using the same encoding as `Grid::completed_minigrid`
+    pub status: Vec<Cell>,
+    pub side_to_move: Cell,
+    /// Minigrid the next move must be played in, `None` if the player can choose any playable minigrid
+    pub forced: Option<usize>,
+    pub ply: u32,
+    pub rules: Ruleset,
+}
+
+/// Whether `f` holds for the cell indices of some line of `k` cells on a `size`×`size` board
+fn any_line(size: usize, k: usize, mut f: impl FnMut(&[usize]) -> bool) -> bool {
+    let mut line = Vec::with_capacity(k);
+    let reach = k as isize - 1;
+    for y in 0..size as isize {
+        for x in 0..size as isize {
+            // Rows, columns, diagonals and anti-diagonals starting at (x, y)
+            for (dx, dy) in [(1, 0), (0, 1), (1, 1), (-1, 1)] {
+                let (end_x, end_y) = (x + dx * reach, y + dy * reach);
+                if end_x < 0 || end_x >= size as isize || end_y >= size as isize {
+                    continue;
+                }
+                line.clear();
+                line.extend(
+                    (0..k as isize).map(|i| ((x + i * dx) + (y + i * dy) * size as isize) as usize),
+                );
+                if f(&line) {
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
+impl NGameState {
+    /// Empty board with Cross to move
+    pub fn new(size: usize, k: usize, rules: Ruleset) -> NGameState {
+        assert!(
+            k >= 1 && k <= size,
+            "k must be between 1 and the board size"
+        );
+        NGameState {
+            size,
+            k,
+            cells: vec![Cell::Empty; size.pow(4)],
+            status: vec![Cell::Empty; size * size],
+            side_to_move: Cell::Cross,
+            forced: None,
+            ply: 0,
+            rules,
+        }
+    }
+
+    /// Cells of one minigrid, row-major
+    pub fn minigrid(&self, meta: usize) -> &[Cell] {
+        let area = self.size * self.size;
+        &self.cells[meta * area..(meta + 1) * area]
+    }
+    pub fn get(&self, coord: NCoord) -> Cell {
+        self.minigrid(coord.meta as usize)[coord.cell as usize]
+    }
+    fn is_full(&self, meta: usize) -> bool {
+        self.minigrid(meta).iter().all(|&c| c != Cell::Empty)
+    }
+    /// Whether the minigrid can still be played in under `self.rules`
+    pub fn is_playable(&self, meta: usize) -> bool {
+        match self.status[meta] {
+            Cell::Empty => true,
+            Cell::Cross | Cell::Circle => self.rules.play_in_won_minigrids && !self.is_full(meta),
+            Cell::Draw => false,
+        }
+    }
+    /// Minigrids the side to move may play in
+    pub fn allowed_minigrids(&self) -> Vec<usize> {
+        match self.forced {
+            Some(meta) if self.is_playable(meta) => vec![meta],
+            _ => (0..self.status.len())
+                .filter(|&m| self.is_playable(m))
+                .collect(),
+        }
+    }
+
+    /// Whether `symbol` owns a full meta line (drawn minigrids count only with `draw_counts_for_both`)
+    pub fn has_meta_line(&self, symbol: Cell) -> bool {
+        let wildcard = |c| self.rules.draw_counts_for_both && c == Cell::Draw;
+        any_line(self.size, self.k, |line| {
+            line.iter().any(|&m| self.status[m] == symbol)
+                && line
+                    .iter()
+                    .all(|&m| self.status[m] == symbol || wildcard(self.status[m]))
+        })
+    }
+}
+
+impl Game for NGameState {
+    type Move = NCoord;
+
+    fn side_to_move(&self) -> Cell {
+        self.side_to_move
+    }
+    fn result(&self) -> GameResult {
+        let count = |symbol| self.status.iter().filter(|&&c| c == symbol).count() as u32;
+        self.rules.tiebreak.result(
+            (
+                self.has_meta_line(Cell::Cross),
+                self.has_meta_line(Cell::Circle),
+            ),
+            (0..self.status.len()).any(|m| self.is_playable(m)),
+            (count(Cell::Cross), count(Cell::Circle)),
+        )
+    }
+    fn legal_moves(&self) -> Vec<NCoord> {
+        let mut moves = Vec::new();
+        for meta in self.allowed_minigrids() {
+            for (cell, &c) in self.minigrid(meta).iter().enumerate() {
+                if c == Cell::Empty {
+                    moves.push(NCoord {
+                        meta: meta as u16,
+                        cell: cell as u16,
+                    });
+                }
+            }
+        }
+        moves
+    }
+    fn random_legal_move<R: Rng>(&self, rng: &mut R) -> Option<NCoord> {
+        let moves = self.legal_moves();
+        if moves.is_empty() {
+            return None;
+        }
+        Some(moves[rng.gen_range(0..moves.len())])
+    }
+    fn play(&mut self, mv: NCoord) {
+        let (meta, cell) = (mv.meta as usize, mv.cell as usize);
+        let area = self.size * self.size;
+        self.cells[meta * area + cell] = self.side_to_move;
+
+        // A won minigrid keeps its winner (only playable with `Ruleset::play_in_won_minigrids`)
+        if self.status[meta] == Cell::Empty {
+            let minigrid = self.minigrid(meta);
+            if any_line(self.size, self.k, |line| {
+                line.iter().all(|&c| minigrid[c] == self.side_to_move)
+            }) {
+                self.status[meta] = self.side_to_move;
+            } else if self.is_full(meta) {
+                self.status[meta] = Cell::Draw;
+            }
+        }
+
+        // The opponent is sent to the minigrid matching the cell just played, if it's still playable
+        self.forced = self.is_playable(cell).then_some(cell);
+        self.side_to_move = opponent(self.side_to_move);
+        self.ply += 1;
+    }
+}
+
+impl MetaGame for NGameState {
+    fn size(&self) -> usize {
+        self.size
+    }
+    fn move_indices(&self, mv: NCoord) -> (usize, usize) {
+        (mv.meta as usize, mv.cell as usize)
+    }
+    fn completes_line(&self, mv: NCoord, symbol: Cell) -> bool {
+        let minigrid = self.minigrid(mv.meta as usize);
+        any_line(self.size, self.k, |line| {
+            line.iter()
+                .all(|&c| c == mv.cell as usize || minigrid[c] == symbol)
+        })
+    }
+}
+
+/// Draws the board like `crate::defs::print_grid`, followed by the minigrid statuses
+impl fmt::Display for NGameState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let n = self.size;
+        for meta_y in 0..n {
+            for local_y in 0..n {
+                for meta_x in 0..n {
+                    let minigrid = self.minigrid(meta_x + meta_y * n);
+                    for local_x in 0..n {
+                        write!(f, " {}", cell_char(minigrid[local_x + local_y * n]))?;
+                    }
+                    if meta_x < n - 1 {
+                        write!(f, " |")?;
+                    }
+                }
+                writeln!(f)?;
+            }
+            if meta_y < n - 1 {
+                let segment = "-".repeat(2 * n + 1);
+                writeln!(f, "{}", vec![segment; n].join("+"))?;
+            }
+        }
+
+        writeln!(f)?;
+        for meta_y in 0..n {
+            for meta_x in 0..n {
+                write!(f, " {}", cell_char(self.status[meta_x + meta_y * n]))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcts::MCTSPlayer;
+    use crate::perft::perft;
+    use crate::state::GameState;
+    use crate::testutil::random_game;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn nperft(state: &NGameState, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        if state.result().is_over() {
+            return 0;
+        }
+        state
+            .legal_moves()
+            .into_iter()
+            .map(|mv| {
+                let mut child = state.clone();
+                child.play(mv);
+                nperft(&child, depth - 1)
+            })
+            .sum()
+    }
+
+    #[test]
+    fn three_by_three_matches_perft_for_every_preset() {
+        for (name, rules) in Ruleset::PRESETS {
+            let state = NGameState::new(3, 3, rules);
+            assert_eq!(nperft(&state, 4), 55080, "{name}");
+            assert_eq!(
+                nperft(&state, 4),
+                perft(&mut GameState::new(rules), 4),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn three_by_three_matches_bitboard_in_random_games() {
+        let mut rng = StdRng::seed_from_u64(14);
+        for (name, rules) in Ruleset::PRESETS {
+            for _ in 0..200 {
+                let positions = random_game(rules, &mut rng);
+                let moves = positions.last().unwrap().history();
+                let mut nstate = NGameState::new(3, 3, rules);
+                for (state, coord) in positions.iter().zip(moves.map(Some).chain([None])) {
+                    assert_eq!(nstate.result(), state.result(), "{name}");
+                    assert_eq!(
+                        nstate.legal_moves().len(),
+                        state.legal_moves().len(),
+                        "{name}"
+                    );
+                    if let Some(coord) = coord {
+                        nstate.play(NCoord {
+                            meta: coord.meta_index() as u16,
+                            cell: coord.cell_index() as u16,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mcts_plays_legal_moves_on_four_by_four() {
+        let mut state = NGameState::new(4, 3, Ruleset::STANDARD);
+        let player = MCTSPlayer::new(1.4, 500);
+        for _ in 0..4 {
+            let mv = player.search(&state);
+            assert!(state.legal_moves().contains(&mv));
+            state.play(mv);
+        }
+    }
+}
@@ -0,0 +1,319 @@
+//! Single-line position notation, similar to FEN in chess:
+//!
+//! ```text
+//! ....O..../........./........./........./X......../........./........./........./......... ......... X 4
+//! ```
+//!
+//! Four fields separated by a single space:
+//! 1. the 81 cells, as 9 minigrids separated by `/` (row-major over the meta board), each one written
+//!    as its 9 cells row-major, with `X`, `O` or `.` for an empty cell;
+//! 2. the status of the 9 minigrids, with `X`/`O` if won, `#` if drawn and `.` if still open;
+//!    it must agree with the cells (with `Ruleset::play_in_won_minigrids` it tells which player won first);
+//! 3. the side to move, `X` or `O`;
+//! 4. the minigrid the next move is forced into, as its index `meta_x + 3 * meta_y` (0-8),
+//!    or `-` if the move is free.
+//!
+//! The move history is not part of the notation: a parsed `GameState` starts with an empty history.
+//! Parsed positions must also pass `GameState::validate`.
+
+use crate::bitboard::BitBoard;
+use crate::defs::{cell_char, Cell, Coord};
+use crate::rules::Ruleset;
+use crate::state::GameState;
+use crate::validate::PositionError;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NotationError {
+    /// The notation doesn't have exactly 4 space separated fields
+    WrongFieldCount(usize),
+    /// The cells field doesn't have exactly 9 minigrids
+    WrongMinigridCount(usize),
+    /// A minigrid doesn't have exactly 9 cells
+    WrongMinigridLength {
+        minigrid: usize,
+        length: usize,
+    },
+    InvalidCell(char),
+    /// The status field doesn't have exactly 9 characters
+    WrongStatusLength(usize),
+    InvalidStatus(char),
+    InvalidSide(String),
+    InvalidForced(String),
+    /// The forced minigrid can't be played in
+    ForcedIntoClosedMinigrid(u8),
+    /// The position is well formed but can't happen in a legal game
+    InvalidPosition(PositionError),
+}
+
+impl fmt::Display for NotationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NotationError::WrongFieldCount(n) => write!(f, "expected 4 fields, found {n}"),
+            NotationError::WrongMinigridCount(n) => write!(f, "expected 9 minigrids, found {n}"),
+            NotationError::WrongMinigridLength { minigrid, length } => {
+                write!(f, "minigrid {minigrid} has {length} cells instead of 9")
+            }
+            NotationError::InvalidCell(c) => write!(f, "invalid cell '{c}'"),
+            NotationError::WrongStatusLength(n) => {
+                write!(f, "expected 9 minigrid statuses, found {n}")
+            }
+            NotationError::InvalidStatus(c) => write!(f, "invalid minigrid status '{c}'"),
+            NotationError::InvalidSide(s) => write!(f, "invalid side to move '{s}'"),
+            NotationError::InvalidForced(s) => write!(f, "invalid forced minigrid '{s}'"),
+            NotationError::ForcedIntoClosedMinigrid(meta) => {
+                write!(f, "forced minigrid {meta} is already closed")
+            }
+            NotationError::InvalidPosition(e) => write!(f, "invalid position: {e}"),
+        }
+    }
+}
+impl std::error::Error for NotationError {}
+
+fn parse_cell(c: char) -> Result<Cell, NotationError> {
+    match c {
+        'X' => Ok(Cell::Cross),
+        'O' => Ok(Cell::Circle),
+        '.' => Ok(Cell::Empty),
+        _ => Err(NotationError::InvalidCell(c)),
+    }
+}
+fn parse_status(c: char) -> Result<Cell, NotationError> {
+    match c {
+        'X' => Ok(Cell::Cross),
+        'O' => Ok(Cell::Circle),
+        '#' => Ok(Cell::Draw),
+        '.' => Ok(Cell::Empty),
+        _ => Err(NotationError::InvalidStatus(c)),
+    }
+}
+
+/// Parse a position played under `rules`
+pub fn parse_position(text: &str, rules: Ruleset) -> Result<GameState, NotationError> {
+    let fields: Vec<&str> = text.split(' ').collect();
+    let [cells, status, side, forced] = fields.as_slice() else {
+        return Err(NotationError::WrongFieldCount(fields.len()));
+    };
+
+    // Cells
+    let minigrids: Vec<&str> = cells.split('/').collect();
+    if minigrids.len() != 9 {
+        return Err(NotationError::WrongMinigridCount(minigrids.len()));
+    }
+    let mut board = BitBoard::default();
+    for (meta, minigrid) in minigrids.iter().enumerate() {
+        let length = minigrid.chars().count();
+        if length != 9 {
+            return Err(NotationError::WrongMinigridLength {
+                minigrid: meta,
+                length,
+            });
+        }
+        for (cell, c) in minigrid.chars().enumerate() {
+            board.set(Coord::from_indices(meta, cell), parse_cell(c)?);
+        }
+    }
+
+    // Minigrid statuses, checked against the cells by `GameState::validate`
+    let length = status.chars().count();
+    if length != 9 {
+        return Err(NotationError::WrongStatusLength(length));
+    }
+    for (meta, c) in status.chars().enumerate() {
+        match parse_status(c)? {
+            Cell::Cross => board.won[0] |= 1 << meta,
+            Cell::Circle => board.won[1] |= 1 << meta,
+            Cell::Draw => board.drawn |= 1 << meta,
+            _ => {}
+        }
+    }
+
+    let side_to_move = match *side {
+        "X" => Cell::Cross,
+        "O" => Cell::Circle,
+        _ => return Err(NotationError::InvalidSide(side.to_string())),
+    };
+
+    let forced = match *forced {
+        "-" => None,
+        _ => match forced.parse::<u8>() {
+            Ok(meta) if meta < 9 => Some(meta),
+            _ => return Err(NotationError::InvalidForced(forced.to_string())),
+        },
+    };
+    if let Some(meta) = forced {
+        if board.playable_mask(rules) & (1 << meta) == 0 {
+            return Err(NotationError::ForcedIntoClosedMinigrid(meta));
+        }
+    }
+
+    let state = GameState::from_board(board, side_to_move, forced, rules);
+    state.validate().map_err(NotationError::InvalidPosition)?;
+    Ok(state)
+}
+
+impl fmt::Display for GameState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for meta in 0..9 {
+            if meta > 0 {
+                write!(f, "/")?;
+            }
+            for cell in 0..9 {
+                write!(
+                    f,
+                    "{}",
+                    cell_char(self.board.get(Coord::from_indices(meta, cell)))
+                )?;
+            }
+        }
+        write!(f, " ")?;
+        for meta in 0..9 {
+            write!(f, "{}", cell_char(self.board.minigrid_status(meta)))?;
+        }
+        write!(f, " {} ", cell_char(self.side_to_move))?;
+        match self.forced {
+            Some(meta) => write!(f, "{meta}"),
+            None => write!(f, "-"),
+        }
+    }
+}
+impl FromStr for GameState {
+    type Err = NotationError;
+
+    /// Parse a position with the standard rules, see `parse_position`
+    fn from_str(text: &str) -> Result<GameState, NotationError> {
+        parse_position(text, Ruleset::STANDARD)
+    }
+}
+
+/// Error when parsing a move written like `e5`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MoveParseError(pub String);
+
+impl fmt::Display for MoveParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid move '{}', expected a column a-i and a row 1-9",
+            self.0
+        )
+    }
+}
+impl std::error::Error for MoveParseError {}
+
+/// Moves are written as the column (`a`-`i`, left to right) and the row (`1`-`9`, top to bottom)
+/// of the cell on the whole 9x9 board, so `e5` is the center of the central minigrid.
+impl fmt::Display for Coord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let column = (b'a' + self.meta_x * 3 + self.x) as char;
+        let row = self.meta_y * 3 + self.y + 1;
+        write!(f, "{column}{row}")
+    }
+}
+impl FromStr for Coord {
+    type Err = MoveParseError;
+
+    fn from_str(text: &str) -> Result<Coord, MoveParseError> {
+        let error = || MoveParseError(text.to_string());
+        let &[column, row] = text.as_bytes() else {
+            return Err(error());
+        };
+        let column = column.to_ascii_lowercase();
+        if !(b'a'..=b'i').contains(&column) || !(b'1'..=b'9').contains(&row) {
+            return Err(error());
+        }
+        let (column, row) = (column - b'a', row - b'1');
+        Ok(Coord {
+            meta_x: column / 3,
+            meta_y: row / 3,
+            x: column % 3,
+            y: row % 3,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{random_position, EMPTY};
+    use crate::validate::PositionError;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn random_positions_round_trip() {
+        let mut rng = StdRng::seed_from_u64(9);
+        for (name, rules) in Ruleset::PRESETS {
+            for _ in 0..200 {
+                let state = random_position(rules, rng.gen_range(0..81), &mut rng);
+                let text = state.to_string();
+                let parsed = parse_position(&text, rules).unwrap();
+                // The notation has no history, everything else must match
+                assert_eq!(parsed.board, state.board, "{name}: {text}");
+                assert_eq!(parsed.side_to_move, state.side_to_move, "{name}: {text}");
+                assert_eq!(parsed.forced, state.forced, "{name}: {text}");
+                assert_eq!(parsed.ply, state.ply, "{name}: {text}");
+                assert_eq!(parsed.hash, state.hash, "{name}: {text}");
+                assert_eq!(parsed.to_string(), text);
+            }
+        }
+    }
+
+    #[test]
+    fn malformed_positions_are_rejected() {
+        let cases = [
+            ("......... X -", NotationError::WrongFieldCount(3)),
+            (
+                "........./........./........./........./........./........./........./......... ......... X -",
+                NotationError::WrongMinigridCount(8),
+            ),
+            (
+                "........./......../........./........./........./........./........./........./......... ......... X -",
+                NotationError::WrongMinigridLength {
+                    minigrid: 1,
+                    length: 8,
+                },
+            ),
+            (
+                "....Z..../........./........./........./........./........./........./........./......... ......... X -",
+                NotationError::InvalidCell('Z'),
+            ),
+            (
+                "........./........./........./........./........./........./........./........./......... ........ X -",
+                NotationError::WrongStatusLength(8),
+            ),
+            (
+                "........./........./........./........./........./........./........./........./......... ....?.... X -",
+                NotationError::InvalidStatus('?'),
+            ),
+            (
+                "........./........./........./........./........./........./........./........./......... ......... Y -",
+                NotationError::InvalidSide("Y".to_string()),
+            ),
+            (
+                "........./........./........./........./........./........./........./........./......... ......... X 9",
+                NotationError::InvalidForced("9".to_string()),
+            ),
+            (
+                "XXX....../O......../........./O......../........./O......../........./........./......... X........ O 0",
+                NotationError::ForcedIntoClosedMinigrid(0),
+            ),
+            (
+                "XX......./........./........./........./........./........./........./........./......... ......... X -",
+                NotationError::InvalidPosition(PositionError::WrongStoneCount {
+                    crosses: 2,
+                    circles: 0,
+                }),
+            ),
+        ];
+        assert!(parse_position(EMPTY, Ruleset::STANDARD).is_ok());
+        for (text, error) in cases {
+            assert_eq!(
+                parse_position(text, Ruleset::STANDARD),
+                Err(error),
+                "{text}"
+            );
+        }
+    }
+}
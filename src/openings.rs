@@ -0,0 +1,177 @@
+//! Opening suites: short starting positions for engine matches, so that games between deterministic or
+//! similar engines don't all follow the same line.
+//!
+//! A suite file has one opening per line, either as the moves played from the empty board in the
+//! notation of `Coord` (e.g. `e5 e4`), or as a position in the notation of `crate::notation`.
+//! Empty lines and lines starting with `#` are ignored.
+
+use crate::defs::Coord;
+use crate::notation::parse_position;
+use crate::rules::Ruleset;
+use crate::state::GameState;
+use crate::symmetry::canonical_key;
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum OpeningError {
+    Io(std::io::Error),
+    /// The line (counted from 1) is neither a legal move sequence nor a valid position
+    InvalidLine {
+        line: usize,
+        text: String,
+    },
+}
+
+impl fmt::Display for OpeningError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OpeningError::Io(e) => write!(f, "{e}"),
+            OpeningError::InvalidLine { line, text } => {
+                write!(f, "line {line} is not a valid opening: '{text}'")
+            }
+        }
+    }
+}
+impl std::error::Error for OpeningError {}
+
+/// Every position `plies` plies after the empty board, keeping one of each set of symmetric positions
+/// (the first one in move generation order); games that are already over are left out
+pub fn generate(plies: u32, rules: Ruleset) -> Vec<GameState> {
+    let mut openings = Vec::new();
+    let mut seen = HashSet::new();
+    extend(&mut GameState::new(rules), plies, &mut seen, &mut openings);
+    openings
+}
+fn extend(state: &mut GameState, plies: u32, seen: &mut HashSet<u64>, out: &mut Vec<GameState>) {
+    // Symmetric positions have the same continuations, so only one of them is explored
+    if state.result().is_over() || !seen.insert(canonical_key(state)) {
+        return;
+    }
+    if plies == 0 {
+        out.push(*state);
+        return;
+    }
+    for &coord in &state.move_list() {
+        state.play(coord);
+        extend(state, plies - 1, seen, out);
+        state.undo();
+    }
+}
+
+/// Parse a suite file, see the module documentation
+pub fn parse(text: &str, rules: Ruleset) -> Result<Vec<GameState>, OpeningError> {
+    let mut openings = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let opening = parse_moves(line, rules)
+            .or_else(|| parse_position(line, rules).ok())
+            .ok_or_else(|| OpeningError::InvalidLine {
+                line: index + 1,
+                text: line.to_string(),
+            })?;
+        openings.push(opening);
+    }
+    Ok(openings)
+}
+fn parse_moves(line: &str, rules: Ruleset) -> Option<GameState> {
+    let mut state = GameState::new(rules);
+    for text in line.split_whitespace() {
+        let coord: Coord = text.parse().ok()?;
+        state.try_play(coord).ok()?;
+    }
+    Some(state)
+}
+
+/// Suite file with one line per opening: its moves if it has a full history, otherwise its position
+pub fn to_string(openings: &[GameState]) -> String {
+    let mut text = String::new();
+    for opening in openings {
+        if opening.history().count() == opening.ply as usize {
+            let moves: Vec<String> = opening.history().map(|c| c.to_string()).collect();
+            text += &moves.join(" ");
+        } else {
+            text += &opening.to_string();
+        }
+        text.push('\n');
+    }
+    text
+}
+
+pub fn load(path: &Path, rules: Ruleset) -> Result<Vec<GameState>, OpeningError> {
+    parse(
+        &std::fs::read_to_string(path).map_err(OpeningError::Io)?,
+        rules,
+    )
+}
+pub fn save(path: &Path, openings: &[GameState]) -> Result<(), OpeningError> {
+    std::fs::write(path, to_string(openings)).map_err(OpeningError::Io)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::X_TO_WIN;
+
+    #[test]
+    fn generated_openings_are_one_of_each_symmetric_set() {
+        // The 81 first moves fall into 15 sets under the 8 symmetries of the board
+        assert_eq!(generate(1, Ruleset::STANDARD).len(), 15);
+
+        let openings = generate(2, Ruleset::STANDARD);
+        let keys: HashSet<u64> = openings.iter().map(canonical_key).collect();
+        assert_eq!(keys.len(), openings.len());
+        assert!(openings.iter().all(|opening| opening.ply == 2));
+
+        let mut all = HashSet::new();
+        let state = GameState::new(Ruleset::STANDARD);
+        for first in state.legal_moves() {
+            let mut state = state;
+            state.play(first);
+            for second in state.legal_moves() {
+                let mut state = state;
+                state.play(second);
+                all.insert(canonical_key(&state));
+            }
+        }
+        assert_eq!(keys, all);
+    }
+
+    #[test]
+    fn suites_round_trip() {
+        let mut openings = generate(2, Ruleset::STANDARD);
+        openings.truncate(5);
+        openings.push(parse_position(X_TO_WIN, Ruleset::STANDARD).unwrap());
+        let text = to_string(&openings);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0].split(' ').count(), 2);
+        assert_eq!(lines[5], X_TO_WIN);
+        assert_eq!(parse(&text, Ruleset::STANDARD).unwrap(), openings);
+
+        let commented = format!("# Suite\n\n{}\n  {}  \n", lines[0], lines[5]);
+        let parsed = parse(&commented, Ruleset::STANDARD).unwrap();
+        assert_eq!(parsed, [openings[0], openings[5]]);
+    }
+
+    #[test]
+    fn invalid_lines_are_reported() {
+        for (suite, line, text) in [
+            ("e5 e5", 1, "e5 e5"),
+            ("e5\n# Comment\n\n z9 ", 4, "z9"),
+            ("e5 e4 e6", 1, "e5 e4 e6"),
+            ("......... ......... X -", 1, "......... ......... X -"),
+        ] {
+            match parse(suite, Ruleset::STANDARD) {
+                Err(OpeningError::InvalidLine {
+                    line: found_line,
+                    text: found_text,
+                }) => assert_eq!((found_line, found_text.as_str()), (line, text)),
+                other => panic!("{suite}: {other:?}"),
+            }
+        }
+    }
+}
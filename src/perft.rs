@@ -0,0 +1,166 @@
+use crate::defs::{Cell, Coord, Grid};
+use crate::state::{opponent, GameState};
+
+/// Number of leaf nodes of the move tree `depth` plies below `state`; finished games have no children
+pub fn perft(state: &mut GameState, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    if state.result().is_over() {
+        return 0;
+    }
+    if depth == 1 {
+        return state.legal_move_count() as u64;
+    }
+
+    let mut nodes = 0;
+    for &coord in &state.move_list() {
+        state.play(coord);
+        nodes += perft(state, depth - 1);
+        state.undo();
+    }
+    nodes
+}
+
+/// Perft of every legal move of `state`, useful to find which subtree disagrees with a reference
+pub fn divide(state: &GameState, depth: u32) -> Vec<(Coord, u64)> {
+    state
+        .legal_moves()
+        .into_iter()
+        .map(|coord| {
+            let mut child = *state;
+            child.play(coord);
+            (coord, perft(&mut child, depth.saturating_sub(1)))
+        })
+        .collect()
+}
+
+/// Same as `perft`, but using the `Grid` move generator, as an independent check of the bitboard code
+pub fn perft_grid(state: &GameState, depth: u32) -> u64 {
+    // `Grid::get_legal_moves` only looks at the local coordinates of the last move
+    let last_move = state
+        .forced
+        .map(|meta| Coord::from_indices(0, meta as usize));
+    grid_nodes(state.grid(), last_move, state.side_to_move, state, depth)
+}
+fn grid_nodes(
+    grid: Grid,
+    last_move: Option<Coord>,
+    player: Cell,
+    state: &GameState,
+    depth: u32,
+) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    if grid.result(state.rules).is_over() {
+        return 0;
+    }
+
+    let mut nodes = 0;
+    for coord in grid.get_legal_moves(last_move, state.rules) {
+        let mut child = grid;
+        child.set(coord, player);
+        child.update_grid();
+        nodes += grid_nodes(child, Some(coord), opponent(player), state, depth - 1);
+    }
+    nodes
+}
+
+/// Empty board
+pub const EMPTY: &str =
+    "........./........./........./........./........./........./........./........./......... ......... X -";
+/// Sent to a minigrid won by X
+const SENT_TO_WON: &str =
+    "XOXO...../X..X..X../.OX.O..O./OO.X..O.O/.O...OX../...XX..../..O..O.X./..X.X...O/...X...OX .XO...... X -";
+/// Sent to a drawn minigrid
+const SENT_TO_DRAWN: &str =
+    "....XOOXO/.O.X.XOXX/XXO.XO..X/OOO.OO.../.OXXOXOO./XOOOXXXXO/O..XXX.X./X..X.OOOO/.X.XOOOXX ..XOO#XO. X -";
+/// Forced into an open minigrid
+const FORCED: &str =
+    ".OX....../X.....X../.OX.O..O./........./......X../........./..O..O.../..X....../......... ..O...... X 5";
+/// Only two minigrids left open, games ending inside the tree
+pub const ENDGAME: &str =
+    ".OOOOXXOX/.XXOXOX.X/XO..OXOOX/X..OO.XX./XOOXXXXOO/.OX.XOX../.OOXX.OOO/XOX.O.XO./OX..OOXX. OXO.XXOO. X -";
+
+/// Reference perft counts: (position in `crate::notation` format, depth, leaf nodes)
+pub const REFERENCE: &[(&str, u32, u64)] = &[
+    (EMPTY, 1, 81),
+    (EMPTY, 2, 720),
+    (EMPTY, 3, 6336),
+    (EMPTY, 4, 55080),
+    (EMPTY, 5, 473256),
+    (SENT_TO_WON, 1, 40),
+    (SENT_TO_WON, 2, 521),
+    (SENT_TO_WON, 3, 6029),
+    (SENT_TO_WON, 4, 72667),
+    (SENT_TO_DRAWN, 1, 9),
+    (SENT_TO_DRAWN, 2, 50),
+    (SENT_TO_DRAWN, 3, 219),
+    (SENT_TO_DRAWN, 4, 740),
+    (FORCED, 1, 9),
+    (FORCED, 2, 126),
+    (FORCED, 3, 1471),
+    (FORCED, 4, 16733),
+    (ENDGAME, 1, 7),
+    (ENDGAME, 2, 32),
+    (ENDGAME, 3, 77),
+    (ENDGAME, 4, 191),
+];
+
+/// Run every entry of `REFERENCE`, returning the ones that don't match as (entry, actual count)
+pub fn verify() -> Vec<((&'static str, u32, u64), u64)> {
+    REFERENCE
+        .iter()
+        .filter_map(|&(position, depth, expected)| {
+            let mut state: GameState = position.parse().unwrap();
+            let nodes = perft(&mut state, depth);
+            (nodes != expected).then_some(((position, depth, expected), nodes))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notation::parse_position;
+    use crate::rules::Ruleset;
+
+    #[test]
+    fn reference_counts_match() {
+        assert_eq!(verify(), vec![]);
+    }
+
+    #[test]
+    fn grid_generator_matches_bitboard() {
+        for &(position, depth, expected) in REFERENCE.iter().filter(|e| e.1 <= 3) {
+            let state: GameState = position.parse().unwrap();
+            assert_eq!(
+                perft_grid(&state, depth),
+                expected,
+                "{position} depth {depth}"
+            );
+        }
+    }
+
+    #[test]
+    fn grid_generator_matches_bitboard_for_every_ruleset() {
+        for (name, rules) in Ruleset::PRESETS {
+            for position in [SENT_TO_WON, SENT_TO_DRAWN, ENDGAME] {
+                let mut state = parse_position(position, rules).unwrap();
+                assert_eq!(
+                    perft_grid(&state, 3),
+                    perft(&mut state, 3),
+                    "{name}: {position}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn divide_sums_to_perft() {
+        let state: GameState = SENT_TO_WON.parse().unwrap();
+        let total: u64 = divide(&state, 3).iter().map(|&(_, nodes)| nodes).sum();
+        assert_eq!(total, 6029);
+    }
+}
@@ -0,0 +1,295 @@
+//! Game record format, inspired by PGN:
+//!
+//! ```text
+//! [X "MCTS(exploration_weight=1.5, simulation_steps=100)"]
+//! [O "Human"]
+//! [Date "2025.01.31"]
+//! [Rules "standard"]
+//! [Result "*"]
+//!
+//! 1. e5 e4 2. e2 d5 3. b5 f6 *
+//! ```
+//!
+//! Each header is a `[Key "Value"]` line, followed by an empty line and the moves (see `Coord`'s notation),
+//! numbered every two plies. The record ends with the result: `1-0` if X won, `0-1` if O won,
+//! `1/2-1/2` for a draw and `*` for an unfinished game.
+//! A game that doesn't start from the empty board has a `Start` header with the starting position
+//! in the notation of `crate::notation`.
+//! A game that didn't end normally (e.g. lost on time) has a `Termination` header with the reason.
+
+use crate::defs::{Cell, Coord, GameResult, Player};
+use crate::notation::{parse_position, MoveParseError, NotationError};
+use crate::rules::{Ruleset, RulesetParseError};
+use crate::runner::{MatchStats, Termination};
+use crate::state::GameState;
+use crate::validate::MoveError;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GameRecord {
+    /// Headers in the order they are written, `Result` excluded
+    pub headers: Vec<(String, String)>,
+    pub moves: Vec<Coord>,
+    pub result: GameResult,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RecordError {
+    InvalidHeader(String),
+    InvalidResult(String),
+    InvalidRules(RulesetParseError),
+    InvalidStart(NotationError),
+    InvalidMove(MoveParseError),
+    /// The move with the given index (from 0) can't be played in its position
+    IllegalMove(usize, Coord, MoveError),
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordError::InvalidHeader(line) => write!(f, "invalid header '{line}'"),
+            RecordError::InvalidResult(s) => write!(f, "invalid result '{s}'"),
+            RecordError::InvalidRules(e) => write!(f, "invalid rules: {e}"),
+            RecordError::InvalidStart(e) => write!(f, "invalid start position: {e}"),
+            RecordError::InvalidMove(e) => write!(f, "{e}"),
+            RecordError::IllegalMove(index, coord, e) => {
+                write!(f, "move {} ({coord}) is illegal: {e}", index + 1)
+            }
+        }
+    }
+}
+impl std::error::Error for RecordError {}
+
+pub fn result_str(result: GameResult) -> &'static str {
+    match result {
+        GameResult::Win(Cell::Cross) => "1-0",
+        GameResult::Win(_) => "0-1",
+        GameResult::Draw => "1/2-1/2",
+        GameResult::Ongoing => "*",
+    }
+}
+fn parse_result(text: &str) -> Result<GameResult, RecordError> {
+    match text {
+        "1-0" => Ok(GameResult::Win(Cell::Cross)),
+        "0-1" => Ok(GameResult::Win(Cell::Circle)),
+        "1/2-1/2" => Ok(GameResult::Draw),
+        "*" => Ok(GameResult::Ongoing),
+        _ => Err(RecordError::InvalidResult(text.to_string())),
+    }
+}
+
+/// Current UTC date as `YYYY.MM.DD`
+pub fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() / 86400)
+        .unwrap_or(0) as i64;
+
+    // Days since 1970-01-01 to civil date (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!("{year:04}.{month:02}.{day:02}")
+}
+
+impl GameRecord {
+    /// Record of a game played by `MatchRunner::run(a, b)`
+    pub fn from_match<A: Player + ?Sized, B: Player + ?Sized>(
+        stats: &MatchStats,
+        a: &A,
+        b: &B,
+    ) -> GameRecord {
+        let (cross, circle) = if stats.a_side == Cell::Cross {
+            (a.name(), b.name())
+        } else {
+            (b.name(), a.name())
+        };
+        let mut headers = vec![
+            ("X".to_string(), cross),
+            ("O".to_string(), circle),
+            ("Date".to_string(), today()),
+            ("Rules".to_string(), stats.final_state.rules.to_string()),
+        ];
+
+        if stats.termination != Termination::Normal {
+            headers.push(("Termination".to_string(), stats.termination.to_string()));
+        }
+
+        let mut start = stats.final_state;
+        while start.undo().is_some() {}
+        if start.ply > 0 {
+            headers.push(("Start".to_string(), start.to_string()));
+        }
+
+        GameRecord {
+            headers,
+            moves: stats.final_state.history().collect(),
+            result: stats.result,
+        }
+    }
+
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Play the recorded moves from the start position, checking that they are legal
+    pub fn replay(&self) -> Result<GameState, RecordError> {
+        let rules = match self.header("Rules") {
+            Some(rules) => rules.parse().map_err(RecordError::InvalidRules)?,
+            None => Ruleset::STANDARD,
+        };
+        let mut state = match self.header("Start") {
+            Some(position) => parse_position(position, rules).map_err(RecordError::InvalidStart)?,
+            None => GameState::new(rules),
+        };
+        for (index, &coord) in self.moves.iter().enumerate() {
+            state
+                .try_play(coord)
+                .map_err(|e| RecordError::IllegalMove(index, coord, e))?;
+        }
+        Ok(state)
+    }
+}
+
+impl fmt::Display for GameRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (key, value) in &self.headers {
+            writeln!(f, "[{key} \"{value}\"]")?;
+        }
+        writeln!(f, "[Result \"{}\"]", result_str(self.result))?;
+        writeln!(f)?;
+
+        for (index, coord) in self.moves.iter().enumerate() {
+            if index % 2 == 0 {
+                write!(f, "{}. ", index / 2 + 1)?;
+            }
+            write!(f, "{coord} ")?;
+        }
+        writeln!(f, "{}", result_str(self.result))
+    }
+}
+impl FromStr for GameRecord {
+    type Err = RecordError;
+
+    fn from_str(text: &str) -> Result<GameRecord, RecordError> {
+        let mut headers = Vec::new();
+        let mut result = None;
+        let mut moves = Vec::new();
+
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            if let Some(header) = line.strip_prefix('[') {
+                let invalid = || RecordError::InvalidHeader(line.to_string());
+                let header = header.strip_suffix(']').ok_or_else(invalid)?;
+                let (key, value) = header.split_once(' ').ok_or_else(invalid)?;
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|v| v.strip_suffix('"'))
+                    .ok_or_else(invalid)?;
+                if key == "Result" {
+                    result = Some(parse_result(value)?);
+                } else {
+                    headers.push((key.to_string(), value.to_string()));
+                }
+                continue;
+            }
+
+            for token in line.split_whitespace() {
+                if token.ends_with('.') {
+                    // Move number
+                    continue;
+                }
+                match parse_result(token) {
+                    Ok(r) => result = Some(r),
+                    Err(_) => moves.push(token.parse().map_err(RecordError::InvalidMove)?),
+                }
+            }
+        }
+
+        Ok(GameRecord {
+            headers,
+            moves,
+            result: result.unwrap_or_default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::MatchRunner;
+    use crate::testutil::X_WON_TOP_LEFT;
+
+    /// Plays a random legal move
+    struct RandomPlayer;
+
+    impl Player for RandomPlayer {
+        fn reset(&self) {}
+        fn name(&self) -> String {
+            "Random".to_string()
+        }
+        fn select_move(&self, state: &GameState) -> Coord {
+            state.random_legal_move(&mut rand::thread_rng()).unwrap()
+        }
+    }
+
+    #[test]
+    fn record_round_trips_through_text_and_replay() {
+        for (name, rules) in Ruleset::PRESETS {
+            for _ in 0..20 {
+                let stats = MatchRunner::new(rules).run(&RandomPlayer, &RandomPlayer);
+                let record = GameRecord::from_match(&stats, &RandomPlayer, &RandomPlayer);
+                let parsed: GameRecord = record.to_string().parse().unwrap();
+                assert_eq!(parsed, record, "{name}");
+                assert_eq!(parsed.replay().unwrap(), stats.final_state, "{name}");
+            }
+        }
+    }
+
+    #[test]
+    fn module_example_is_a_legal_record() {
+        let example: String = include_str!("record.rs")
+            .lines()
+            .skip_while(|line| *line != "//! ```text")
+            .skip(1)
+            .take_while(|line| *line != "//! ```")
+            .map(|line| format!("{}\n", line.trim_start_matches("//!").trim()))
+            .collect();
+        let record: GameRecord = example.parse().unwrap();
+        assert_eq!(record.header("O"), Some("Human"));
+        assert_eq!(record.moves.len(), 6);
+        assert_eq!(record.result, GameResult::Ongoing);
+        assert_eq!(record.replay().unwrap().ply, 6);
+    }
+
+    #[test]
+    fn record_from_a_start_position() {
+        let start = parse_position(X_WON_TOP_LEFT, Ruleset::STANDARD).unwrap();
+        let mut runner = MatchRunner::new(Ruleset::STANDARD);
+        runner.start = start;
+        runner.a_side = Cell::Circle;
+        let stats = runner.run(&RandomPlayer, &RandomPlayer);
+
+        let record = GameRecord::from_match(&stats, &RandomPlayer, &RandomPlayer);
+        assert_eq!(record.header("Start"), Some(start.to_string().as_str()));
+        assert_eq!(
+            record.moves.len(),
+            (stats.final_state.ply - start.ply) as usize
+        );
+        let parsed: GameRecord = record.to_string().parse().unwrap();
+        let replayed = parsed.replay().unwrap();
+        assert_eq!(replayed.board, stats.final_state.board);
+        assert_eq!(replayed.to_string(), stats.final_state.to_string());
+        assert_eq!(parsed.result, stats.result);
+    }
+}
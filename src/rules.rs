@@ -0,0 +1,166 @@
+use crate::defs::Tiebreak;
+use std::fmt;
+use std::str::FromStr;
+
+/// Rule variants that change move generation and result detection
+#[derive(Clone, Copy, Debug, PartialEq, Hash, Eq, Default)]
+pub struct Ruleset {
+    /// Won minigrids stay playable (and players can be sent to them) until they are full
+    pub play_in_won_minigrids: bool,
+    /// A drawn minigrid counts for both players when looking for a meta line;
+    /// a line still needs at least one minigrid won by its owner
+    pub draw_counts_for_both: bool,
+    pub tiebreak: Tiebreak,
+}
+
+impl Ruleset {
+    /// Won and drawn minigrids are closed, a game without a meta line is a draw
+    pub const STANDARD: Ruleset = Ruleset {
+        play_in_won_minigrids: false,
+        draw_counts_for_both: false,
+        tiebreak: Tiebreak::None,
+    };
+    /// Rules of the CodinGame arena: a game without a meta line goes to whoever won more minigrids
+    pub const CODINGAME: Ruleset = Ruleset {
+        tiebreak: Tiebreak::MostMinigrids,
+        ..Ruleset::STANDARD
+    };
+    /// Only full minigrids are closed
+    pub const OPEN_WON_MINIGRIDS: Ruleset = Ruleset {
+        play_in_won_minigrids: true,
+        ..Ruleset::STANDARD
+    };
+    /// Drawn minigrids are wildcards for meta lines
+    pub const WILDCARD_DRAWS: Ruleset = Ruleset {
+        draw_counts_for_both: true,
+        ..Ruleset::STANDARD
+    };
+
+    pub const PRESETS: [(&'static str, Ruleset); 4] = [
+        ("standard", Ruleset::STANDARD),
+        ("codingame", Ruleset::CODINGAME),
+        ("open-won-minigrids", Ruleset::OPEN_WON_MINIGRIDS),
+        ("wildcard-draws", Ruleset::WILDCARD_DRAWS),
+    ];
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RulesetParseError(pub String);
+
+impl fmt::Display for RulesetParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown rule '{}'", self.0)
+    }
+}
+impl std::error::Error for RulesetParseError {}
+
+/// Written as a comma separated list of the rules that differ from `Ruleset::STANDARD`
+/// (`play-in-won`, `draws-count-both`, `most-minigrids`), or `standard` if there are none
+impl fmt::Display for Ruleset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut flags = Vec::new();
+        if self.play_in_won_minigrids {
+            flags.push("play-in-won");
+        }
+        if self.draw_counts_for_both {
+            flags.push("draws-count-both");
+        }
+        if self.tiebreak == Tiebreak::MostMinigrids {
+            flags.push("most-minigrids");
+        }
+        if flags.is_empty() {
+            write!(f, "standard")
+        } else {
+            write!(f, "{}", flags.join(","))
+        }
+    }
+}
+/// Accepts the format written by `Display`, where preset names can also be used as flags
+impl FromStr for Ruleset {
+    type Err = RulesetParseError;
+
+    fn from_str(text: &str) -> Result<Ruleset, RulesetParseError> {
+        let mut rules = Ruleset::STANDARD;
+        for flag in text.split(',').map(str::trim) {
+            let preset = Ruleset::PRESETS.iter().find(|(name, _)| *name == flag);
+            match (flag, preset) {
+                (_, Some((_, preset))) => {
+                    rules.play_in_won_minigrids |= preset.play_in_won_minigrids;
+                    rules.draw_counts_for_both |= preset.draw_counts_for_both;
+                    if preset.tiebreak != Tiebreak::None {
+                        rules.tiebreak = preset.tiebreak;
+                    }
+                }
+                ("play-in-won", _) => rules.play_in_won_minigrids = true,
+                ("draws-count-both", _) => rules.draw_counts_for_both = true,
+                ("most-minigrids", _) => rules.tiebreak = Tiebreak::MostMinigrids,
+                _ => return Err(RulesetParseError(flag.to_string())),
+            }
+        }
+        Ok(rules)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_round_trip_by_name_and_display() {
+        for (name, rules) in Ruleset::PRESETS {
+            assert_eq!(name.parse::<Ruleset>(), Ok(rules), "{name}");
+            assert_eq!(rules.to_string().parse::<Ruleset>(), Ok(rules), "{name}");
+        }
+        assert_eq!(Ruleset::STANDARD.to_string(), "standard");
+        assert_eq!(Ruleset::CODINGAME.to_string(), "most-minigrids");
+        assert_eq!(Ruleset::default(), Ruleset::STANDARD);
+    }
+
+    #[test]
+    fn every_combination_of_flags_round_trips() {
+        for play_in_won_minigrids in [false, true] {
+            for draw_counts_for_both in [false, true] {
+                for tiebreak in [Tiebreak::None, Tiebreak::MostMinigrids] {
+                    let rules = Ruleset {
+                        play_in_won_minigrids,
+                        draw_counts_for_both,
+                        tiebreak,
+                    };
+                    assert_eq!(rules.to_string().parse::<Ruleset>(), Ok(rules), "{rules}");
+                }
+            }
+        }
+        // Spaces around the flags are allowed
+        assert_eq!(
+            " play-in-won, most-minigrids".parse::<Ruleset>(),
+            Ok(Ruleset {
+                play_in_won_minigrids: true,
+                ..Ruleset::CODINGAME
+            })
+        );
+    }
+
+    #[test]
+    fn presets_combine_as_flags() {
+        assert_eq!(
+            "codingame,open-won-minigrids".parse::<Ruleset>(),
+            Ok(Ruleset {
+                play_in_won_minigrids: true,
+                ..Ruleset::CODINGAME
+            })
+        );
+        assert_eq!(
+            "wildcard-draws,standard".parse::<Ruleset>(),
+            Ok(Ruleset::WILDCARD_DRAWS)
+        );
+    }
+
+    #[test]
+    fn unknown_flags_are_rejected() {
+        assert_eq!(
+            "standard,fast".parse::<Ruleset>(),
+            Err(RulesetParseError("fast".to_string()))
+        );
+        assert_eq!("".parse::<Ruleset>(), Err(RulesetParseError(String::new())));
+    }
+}
@@ -0,0 +1,480 @@
+use crate::bitboard::player_index;
+use crate::clock::{TimeControl, TimeLeft};
+use crate::defs::{cell_char, print_grid, Cell, Coord, GameResult, Player};
+use crate::rules::Ruleset;
+use crate::solver::{empty_cells, solve};
+use crate::state::{opponent, GameState};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// One move of a match
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MoveRecord {
+    pub coord: Coord,
+    pub side: Cell,
+    /// Time spent in `Player::select_move`
+    pub time: Duration,
+}
+
+/// Why a match ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Termination {
+    /// The game was played until its end
+    #[default]
+    Normal,
+    /// The player of the given side went over its time; the opponent wins unless it can't win anymore
+    TimeForfeit(Cell),
+    /// The player of the given side returned an illegal move, which loses the game
+    IllegalMove(Cell),
+    /// The player of the given side resigned, see `Adjudication::resign`
+    Resignation(Cell),
+    /// Both players evaluated the game as drawn, see `Adjudication::draw`
+    DrawAgreed,
+    /// The result was computed by the solver, see `Adjudication::solve_cells`
+    Solved,
+}
+
+/// Positions the solver can visit before giving up, which it then tries again after the next move
+const SOLVE_NODES: u64 = 1_000_000;
+
+/// Evaluation threshold that has to hold for a number of moves in a row
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EvalRule {
+    pub score: f32,
+    pub moves: u32,
+}
+
+/// When to end a match before the game is over; everything is disabled by default
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct Adjudication {
+    /// A player resigns once its evaluation is at most `-score` for `moves` of its moves in a row
+    pub resign: Option<EvalRule>,
+    /// The game is drawn once the evaluations of both players are between `-score` and `score`
+    /// for `moves` moves each in a row
+    pub draw: Option<EvalRule>,
+    /// Finish the game with `crate::solver::solve` once at most this many empty cells are left and the
+    /// solver finds the result quickly enough
+    pub solve_cells: Option<u32>,
+}
+
+#[derive(Clone, Debug)]
+pub struct MatchStats {
+    /// Result of the match, which differs from `final_state.result()` if the game didn't end normally
+    pub result: GameResult,
+    pub termination: Termination,
+    pub number_turns: u8,
+    pub final_state: GameState,
+    /// Side played by the first player passed to `MatchRunner::run`
+    pub a_side: Cell,
+    /// Every move played from the start position, in order
+    pub moves: Vec<MoveRecord>,
+}
+
+impl MatchStats {
+    /// Total time spent choosing moves by the player of `side`
+    pub fn time_used(&self, side: Cell) -> Duration {
+        self.moves
+            .iter()
+            .filter(|m| m.side == side)
+            .map(|m| m.time)
+            .sum()
+    }
+}
+
+/// Receives every move played by a `MatchRunner`
+pub trait Observer: Send + Sync {
+    /// Called after `record` was played, `state` being the position after the move
+    fn on_move(&self, state: &GameState, record: &MoveRecord);
+}
+impl<F: Fn(&GameState, &MoveRecord) + Send + Sync> Observer for F {
+    fn on_move(&self, state: &GameState, record: &MoveRecord) {
+        self(state, record)
+    }
+}
+
+/// Plays matches between two players with the given options
+pub struct MatchRunner<'a> {
+    /// Position every match starts from
+    pub start: GameState,
+    /// Side played by the first player passed to `run`, the other player gets the opposite one
+    pub a_side: Cell,
+    /// Print every move and the board after it
+    pub verbose: bool,
+    pub time_control: TimeControl,
+    /// Time a player can go over its clock before losing on time, to absorb the overhead of the runner
+    pub overstep_grace: Duration,
+    pub adjudication: Adjudication,
+    pub observers: Vec<Box<dyn Observer + 'a>>,
+}
+
+impl<'a> MatchRunner<'a> {
+    /// Quiet runner starting from the empty board, with the first player as X
+    pub fn new(rules: Ruleset) -> MatchRunner<'a> {
+        MatchRunner {
+            start: GameState::new(rules),
+            a_side: Cell::Cross,
+            verbose: false,
+            time_control: TimeControl::None,
+            overstep_grace: Duration::from_millis(20),
+            adjudication: Adjudication::default(),
+            observers: Vec::new(),
+        }
+    }
+
+    /// Play a whole match, `a` playing `self.a_side`
+    pub fn run(&self, a: &dyn Player, b: &dyn Player) -> MatchStats {
+        a.reset();
+        b.reset();
+
+        let mut state = self.start;
+        let mut moves = Vec::new();
+        // Time left to each player, indexed by `player_index`
+        let mut clocks = [self.time_control.initial(); 2];
+        // Moves in a row each player evaluated as lost, indexed by `player_index`
+        let mut losing_moves = [0; 2];
+        // Plies in a row both players evaluated as drawn
+        let mut drawn_plies = 0;
+        let stats = |result, termination, state: GameState, moves| MatchStats {
+            result,
+            termination,
+            number_turns: state.ply,
+            final_state: state,
+            a_side: self.a_side,
+            moves,
+        };
+        loop {
+            let result = state.result();
+            if result.is_over() {
+                return stats(result, Termination::Normal, state, moves);
+            }
+            if let Some(cells) = self.adjudication.solve_cells {
+                if empty_cells(&state) <= cells {
+                    if let Some(result) = solve(&state, SOLVE_NODES) {
+                        return stats(result, Termination::Solved, state, moves);
+                    }
+                }
+            }
+
+            let side = state.side_to_move;
+            let player = if side == self.a_side { a } else { b };
+            let clock = &mut clocks[player_index(side)];
+            let start = Instant::now();
+            let coord = match self.time_control {
+                TimeControl::None => player.select_move(&state),
+                control => player.select_move_timed(
+                    &state,
+                    &TimeLeft {
+                        control,
+                        remaining: *clock,
+                    },
+                ),
+            };
+            let record = MoveRecord {
+                coord,
+                side,
+                time: start.elapsed(),
+            };
+
+            if record.time > clock.saturating_add(self.overstep_grace) {
+                // Like losing on time in chess, it's a draw if the opponent couldn't have won anyway
+                let result = if state.board.can_still_win(opponent(side), state.rules) {
+                    GameResult::Win(opponent(side))
+                } else {
+                    GameResult::Draw
+                };
+                return stats(result, Termination::TimeForfeit(side), state, moves);
+            }
+            match self.time_control {
+                TimeControl::Increment { increment, .. } => {
+                    *clock = clock.saturating_sub(record.time) + increment
+                }
+                TimeControl::None | TimeControl::PerMove(_) => {}
+            }
+
+            if state.try_play(coord).is_err() {
+                let result = GameResult::Win(opponent(side));
+                return stats(result, Termination::IllegalMove(side), state, moves);
+            }
+            moves.push(record);
+            if self.verbose {
+                println!(
+                    "{} plays {coord} ({:.3}s)",
+                    cell_char(side),
+                    record.time.as_secs_f64()
+                );
+                print_grid(&state.grid());
+            }
+            for observer in &self.observers {
+                observer.on_move(&state, &record);
+            }
+
+            let evaluation = player.evaluation();
+            if let Some(rule) = self.adjudication.resign {
+                let losing = &mut losing_moves[player_index(side)];
+                match evaluation {
+                    Some(score) if score <= -rule.score => *losing += 1,
+                    _ => *losing = 0,
+                }
+                if *losing >= rule.moves && !state.result().is_over() {
+                    let result = GameResult::Win(opponent(side));
+                    return stats(result, Termination::Resignation(side), state, moves);
+                }
+            }
+            if let Some(rule) = self.adjudication.draw {
+                match evaluation {
+                    Some(score) if score.abs() <= rule.score => drawn_plies += 1,
+                    _ => drawn_plies = 0,
+                }
+                if drawn_plies >= 2 * rule.moves && !state.result().is_over() {
+                    return stats(GameResult::Draw, Termination::DrawAgreed, state, moves);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Termination {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Termination::Normal => write!(f, "normal"),
+            Termination::TimeForfeit(side) => write!(f, "{} lost on time", cell_char(*side)),
+            Termination::IllegalMove(side) => {
+                write!(f, "{} played an illegal move", cell_char(*side))
+            }
+            Termination::Resignation(side) => write!(f, "{} resigned", cell_char(*side)),
+            Termination::DrawAgreed => write!(f, "draw by agreement"),
+            Termination::Solved => write!(f, "solved"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EvalRuleParseError(pub String);
+
+impl fmt::Display for EvalRuleParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid adjudication rule '{}', expected '<score>:<moves>' with a score between 0 and 1",
+            self.0
+        )
+    }
+}
+impl std::error::Error for EvalRuleParseError {}
+
+/// Written as `<score>:<moves>`
+impl fmt::Display for EvalRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.score, self.moves)
+    }
+}
+impl FromStr for EvalRule {
+    type Err = EvalRuleParseError;
+
+    fn from_str(text: &str) -> Result<EvalRule, EvalRuleParseError> {
+        let error = || EvalRuleParseError(text.to_string());
+        let (score, moves) = text.split_once(':').ok_or_else(error)?;
+        let score: f32 = score.parse().map_err(|_| error())?;
+        if !(0.0..=1.0).contains(&score) {
+            return Err(error());
+        }
+        Ok(EvalRule {
+            score,
+            moves: moves.parse().map_err(|_| error())?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notation::parse_position;
+    use crate::testutil::ENDGAME;
+    use std::sync::Mutex;
+
+    /// Plays the first legal move and always gives the same evaluation
+    struct FixedEvaluation(Option<f32>);
+
+    impl Player for FixedEvaluation {
+        fn reset(&self) {}
+        fn select_move(&self, state: &GameState) -> Coord {
+            state.legal_moves()[0]
+        }
+        fn evaluation(&self) -> Option<f32> {
+            self.0
+        }
+    }
+
+    /// Always plays the same cell, legal or not
+    struct SameCell(Coord);
+
+    impl Player for SameCell {
+        fn reset(&self) {}
+        fn select_move(&self, _: &GameState) -> Coord {
+            self.0
+        }
+    }
+
+    /// Plays the first legal move after `delay`, remembering the time it was given for each move
+    struct Timed {
+        delay: Duration,
+        clocks: Mutex<Vec<Duration>>,
+    }
+
+    impl Timed {
+        fn new(delay: Duration) -> Timed {
+            Timed {
+                delay,
+                clocks: Mutex::new(Vec::new()),
+            }
+        }
+    }
+    impl Player for Timed {
+        fn reset(&self) {}
+        fn select_move(&self, state: &GameState) -> Coord {
+            std::thread::sleep(self.delay);
+            state.legal_moves()[0]
+        }
+        fn select_move_timed(&self, state: &GameState, time: &TimeLeft) -> Coord {
+            self.clocks.lock().unwrap().push(time.remaining);
+            self.select_move(state)
+        }
+    }
+
+    /// X won the center and top-right minigrids and the bottom-left one is drawn, so O can't make a meta line
+    /// anymore; X to move
+    const O_CANNOT_WIN: &str =
+        "OO......./O......../XXX....../O......../XXX....../O......../XOXXOOOXX/O......../O........ ..X.X.#.. X -";
+
+    fn runner(adjudication: Adjudication) -> MatchRunner<'static> {
+        let mut runner = MatchRunner::new(Ruleset::STANDARD);
+        runner.adjudication = adjudication;
+        runner
+    }
+
+    #[test]
+    fn player_resigns_after_enough_losing_moves() {
+        let runner = runner(Adjudication {
+            resign: Some(EvalRule {
+                score: 0.8,
+                moves: 3,
+            }),
+            ..Adjudication::default()
+        });
+        let stats = runner.run(&FixedEvaluation(Some(-0.9)), &FixedEvaluation(Some(0.9)));
+        assert_eq!(stats.termination, Termination::Resignation(Cell::Cross));
+        assert_eq!(stats.result, GameResult::Win(Cell::Circle));
+        // The third move of X is the fifth of the game
+        assert_eq!(stats.moves.len(), 5);
+
+        // Evaluations above the threshold, or no evaluation at all, never resign
+        for evaluation in [Some(-0.7), None] {
+            let stats = runner.run(&FixedEvaluation(evaluation), &FixedEvaluation(evaluation));
+            assert_eq!(stats.termination, Termination::Normal);
+        }
+    }
+
+    #[test]
+    fn draw_is_agreed_when_both_players_see_a_draw() {
+        let runner = runner(Adjudication {
+            draw: Some(EvalRule {
+                score: 0.1,
+                moves: 2,
+            }),
+            ..Adjudication::default()
+        });
+        let stats = runner.run(&FixedEvaluation(Some(0.05)), &FixedEvaluation(Some(-0.05)));
+        assert_eq!(stats.termination, Termination::DrawAgreed);
+        assert_eq!(stats.result, GameResult::Draw);
+        assert_eq!(stats.moves.len(), 4);
+
+        // One player not seeing a draw keeps resetting the counter
+        let stats = runner.run(&FixedEvaluation(Some(0.05)), &FixedEvaluation(Some(0.5)));
+        assert_eq!(stats.termination, Termination::Normal);
+        let stats = runner.run(&FixedEvaluation(Some(0.05)), &FixedEvaluation(None));
+        assert_eq!(stats.termination, Termination::Normal);
+    }
+
+    #[test]
+    fn solver_finishes_the_game() {
+        let mut runner = runner(Adjudication {
+            solve_cells: Some(81),
+            ..Adjudication::default()
+        });
+        runner.start = parse_position(ENDGAME, Ruleset::STANDARD).unwrap();
+        let stats = runner.run(&FixedEvaluation(None), &FixedEvaluation(None));
+        assert_eq!(stats.termination, Termination::Solved);
+        assert_eq!(stats.moves.len(), 0);
+        assert_eq!(Some(stats.result), solve(&runner.start, SOLVE_NODES));
+
+        // One empty cell too many for the solver, until the first move is played
+        runner.adjudication.solve_cells = Some(empty_cells(&runner.start) - 1);
+        let stats = runner.run(&FixedEvaluation(None), &FixedEvaluation(None));
+        assert_eq!(stats.termination, Termination::Solved);
+        assert_eq!(stats.moves.len(), 1);
+    }
+
+    #[test]
+    fn illegal_moves_lose_the_game() {
+        let runner = MatchRunner::new(Ruleset::STANDARD);
+        let corner = Coord::from_indices(0, 0);
+        // X takes the corner and sends O to the top-left minigrid, where O tries the same cell, then the center
+        // of the board
+        for illegal in [corner, Coord::from_indices(4, 4)] {
+            let stats = runner.run(&SameCell(corner), &SameCell(illegal));
+            assert_eq!(stats.termination, Termination::IllegalMove(Cell::Circle));
+            assert_eq!(stats.result, GameResult::Win(Cell::Cross));
+            assert_eq!(stats.moves.len(), 1);
+            assert_eq!(stats.final_state.ply, 1);
+            assert_eq!(stats.final_state.validate(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn increments_are_added_to_the_clock() {
+        let mut runner = MatchRunner::new(Ruleset::STANDARD);
+        runner.time_control = TimeControl::Increment {
+            base: Duration::from_secs(10),
+            increment: Duration::from_secs(1),
+        };
+        let (a, b) = (Timed::new(Duration::ZERO), Timed::new(Duration::ZERO));
+        let stats = runner.run(&a, &b);
+        assert_eq!(stats.termination, Termination::Normal);
+        for player in [a, b] {
+            let clocks = player.clocks.into_inner().unwrap();
+            assert!(clocks.len() > 5);
+            // Moves take almost no time, so every move adds about one second
+            for (moves, &clock) in clocks.iter().enumerate() {
+                let expected = Duration::from_secs(10 + moves as u64);
+                assert!(clock <= expected && clock > expected - Duration::from_millis(500));
+            }
+        }
+    }
+
+    #[test]
+    fn slow_player_loses_on_time() {
+        let mut runner = MatchRunner::new(Ruleset::STANDARD);
+        runner.time_control = TimeControl::PerMove(Duration::from_millis(1));
+        runner.overstep_grace = Duration::ZERO;
+        let (fast, slow) = (
+            Timed::new(Duration::ZERO),
+            Timed::new(Duration::from_millis(20)),
+        );
+
+        let stats = runner.run(&slow, &fast);
+        assert_eq!(stats.termination, Termination::TimeForfeit(Cell::Cross));
+        assert_eq!(stats.result, GameResult::Win(Cell::Circle));
+        assert!(stats.moves.is_empty());
+
+        // Only a draw when the opponent couldn't have won anyway
+        runner.start = parse_position(O_CANNOT_WIN, Ruleset::STANDARD).unwrap();
+        assert!(!runner
+            .start
+            .board
+            .can_still_win(Cell::Circle, Ruleset::STANDARD));
+        let stats = runner.run(&slow, &fast);
+        assert_eq!(stats.termination, Termination::TimeForfeit(Cell::Cross));
+        assert_eq!(stats.result, GameResult::Draw);
+    }
+}
@@ -0,0 +1,176 @@
+use crate::bitboard::{is_win, player_index};
+use crate::defs::GameResult;
+use crate::state::{opponent, GameState};
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Bound {
+    Exact,
+    /// The value is at least the stored one
+    Lower,
+    /// The value is at most the stored one
+    Upper,
+}
+
+/// Empty cells left in the minigrids that can still be played in, an upper bound on the remaining plies
+pub fn empty_cells(state: &GameState) -> u32 {
+    state
+        .board
+        .count_moves_in(state.board.playable_mask(state.rules))
+}
+
+/// Result of `state` with perfect play from both sides, `None` if finding it takes more than `max_nodes`
+/// positions; the number of empty cells is only a rough hint of the work needed, as moves sent to a decided
+/// minigrid can be played anywhere
+pub fn solve(state: &GameState, max_nodes: u64) -> Option<GameResult> {
+    let mut solver = Solver {
+        table: HashMap::new(),
+        nodes: 0,
+        max_nodes,
+    };
+    let mut state = *state;
+    Some(match solver.negamax(&mut state, -1, 1)? {
+        1 => GameResult::Win(state.side_to_move),
+        -1 => GameResult::Win(opponent(state.side_to_move)),
+        _ => GameResult::Draw,
+    })
+}
+
+struct Solver {
+    table: HashMap<u64, (i8, Bound)>,
+    /// Positions visited so far
+    nodes: u64,
+    max_nodes: u64,
+}
+
+impl Solver {
+    /// Value of `state` for the side to move (1 win, 0 draw, -1 loss), exact if it's between `alpha` and `beta`
+    fn negamax(&mut self, state: &mut GameState, mut alpha: i8, mut beta: i8) -> Option<i8> {
+        match state.result() {
+            GameResult::Win(winner) if winner == state.side_to_move => return Some(1),
+            GameResult::Win(_) => return Some(-1),
+            GameResult::Draw => return Some(0),
+            GameResult::Ongoing => {}
+        }
+        self.nodes += 1;
+        if self.nodes > self.max_nodes {
+            return None;
+        }
+
+        let original_alpha = alpha;
+        if let Some(&(value, bound)) = self.table.get(&state.hash) {
+            match bound {
+                Bound::Exact => return Some(value),
+                Bound::Lower => alpha = alpha.max(value),
+                Bound::Upper => beta = beta.min(value),
+            }
+            if alpha >= beta {
+                return Some(value);
+            }
+        }
+
+        // Moves winning their minigrid first, as they are the most likely to cause a cutoff
+        let mut moves = state.legal_moves();
+        let own = state.board.cells[player_index(state.side_to_move)];
+        moves.sort_by_key(|m| !is_win(own[m.meta_index()] | 1 << m.cell_index()));
+
+        let mut best = -1;
+        for coord in moves {
+            state.play(coord);
+            let value = self.negamax(state, -beta, -alpha);
+            state.undo();
+            let value = -value?;
+            best = best.max(value);
+            alpha = alpha.max(value);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        let bound = if best <= original_alpha {
+            Bound::Upper
+        } else if best >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.table.insert(state.hash, (best, bound));
+        Some(best)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::defs::{Cell, Coord};
+    use crate::notation::parse_position;
+    use crate::rules::Ruleset;
+    use crate::testutil::{random_game, X_TO_WIN};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// O must move in the top-right minigrid, where X threatens three lines: whatever O plays, X can then
+    /// play there, or anywhere as every other minigrid is decided, and win it with the top meta row
+    const O_LOSES: &str = "XXXOO..../XXXOO..../XX..X..../OOO....../OOO....../XXXOO..../XXXOO..../XXX....../OOO...... XX.OOXXXO O 2";
+    /// Only the top-right minigrid is left and no meta line can be made with it; each player won 4
+    /// minigrids, so X winning it at once breaks the tie with `Tiebreak::MostMinigrids`
+    const DRAWN: &str = "XXXOO..../OOO....../XX......./OOO....../OOO....../XXX....../XXX....../XXX....../OOO...... XO.OOXXXO X 2";
+
+    /// Value of `state` for the side to move, searching every line
+    fn minimax(state: &mut GameState) -> i8 {
+        match state.result() {
+            GameResult::Win(winner) if winner == state.side_to_move => return 1,
+            GameResult::Win(_) => return -1,
+            GameResult::Draw => return 0,
+            GameResult::Ongoing => {}
+        }
+        let mut best = -1;
+        for coord in state.legal_moves() {
+            state.play(coord);
+            best = best.max(-minimax(state));
+            state.undo();
+        }
+        best
+    }
+
+    #[test]
+    fn solves_won_lost_and_drawn_endgames() {
+        let solved = |text, rules| solve(&parse_position(text, rules).unwrap(), 1_000_000);
+        let x_wins = Some(GameResult::Win(Cell::Cross));
+        assert_eq!(solved(X_TO_WIN, Ruleset::STANDARD), x_wins);
+        assert_eq!(solved(O_LOSES, Ruleset::STANDARD), x_wins);
+        assert_eq!(solved(DRAWN, Ruleset::STANDARD), Some(GameResult::Draw));
+        assert_eq!(solved(DRAWN, Ruleset::CODINGAME), x_wins);
+    }
+
+    #[test]
+    fn gives_up_after_max_nodes() {
+        let state = parse_position(O_LOSES, Ruleset::STANDARD).unwrap();
+        assert_eq!(solve(&state, 1), None);
+
+        let mut state = parse_position(X_TO_WIN, Ruleset::STANDARD).unwrap();
+        state.play(Coord::from_indices(2, 2));
+        assert_eq!(solve(&state, 0), Some(GameResult::Win(Cell::Cross)));
+    }
+
+    #[test]
+    fn agrees_with_minimax_on_random_endgames() {
+        let mut rng = StdRng::seed_from_u64(20);
+        for (_, rules) in Ruleset::PRESETS {
+            for _ in 0..25 {
+                let Some(mut state) = random_game(rules, &mut rng)
+                    .into_iter()
+                    .find(|state| !state.result().is_over() && empty_cells(state) <= 10)
+                else {
+                    continue;
+                };
+                let expected = match minimax(&mut state) {
+                    1 => GameResult::Win(state.side_to_move),
+                    -1 => GameResult::Win(opponent(state.side_to_move)),
+                    _ => GameResult::Draw,
+                };
+                assert_eq!(solve(&state, u64::MAX), Some(expected), "{state}");
+            }
+        }
+    }
+}
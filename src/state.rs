@@ -0,0 +1,193 @@
+use crate::bitboard::{BitBoard, MoveList};
+use crate::defs::{Cell, Coord, GameResult, Grid};
+use crate::rules::Ruleset;
+use crate::zobrist;
+use rand::Rng;
+
+/// Everything needed to continue a game: board, side to move, forced minigrid and move history
+#[derive(Clone, Copy, Debug, PartialEq, Hash, Eq)]
+pub struct GameState {
+    pub board: BitBoard,
+    pub side_to_move: Cell,
+    /// Minigrid the next move must be played in, `None` if the player can choose any open minigrid
+    pub forced: Option<u8>,
+    pub ply: u8,
+    pub rules: Ruleset,
+    /// Zobrist key of board (minigrid statuses included), side to move and forced minigrid, kept up to date
+    /// by `play` and `undo`
+    pub hash: u64,
+    /// Moves played so far, together with the `forced` value they were played under
+    pub(crate) history: [(Coord, Option<u8>); 81],
+    /// Entries of `history` in use, lower than `ply` for positions that were set up with `from_board`
+    pub(crate) history_len: u8,
+}
+
+const NO_MOVE: (Coord, Option<u8>) = (
+    Coord {
+        meta_x: 0,
+        meta_y: 0,
+        x: 0,
+        y: 0,
+    },
+    None,
+);
+
+impl Default for GameState {
+    fn default() -> GameState {
+        GameState::new(Ruleset::STANDARD)
+    }
+}
+
+impl GameState {
+    /// Empty board with Cross to move
+    pub fn new(rules: Ruleset) -> GameState {
+        GameState {
+            board: BitBoard::default(),
+            side_to_move: Cell::Cross,
+            forced: None,
+            ply: 0,
+            rules,
+            hash: 0,
+            history: [NO_MOVE; 81],
+            history_len: 0,
+        }
+    }
+    /// Set up a position without history; `board` must have its minigrid statuses up to date
+    pub fn from_board(
+        board: BitBoard,
+        side_to_move: Cell,
+        forced: Option<u8>,
+        rules: Ruleset,
+    ) -> GameState {
+        let mut state = GameState::new(rules);
+        state.board = board;
+        state.side_to_move = side_to_move;
+        state.forced = forced;
+        state.ply = (0..9)
+            .map(|meta| board.occupied(meta).count_ones())
+            .sum::<u32>() as u8;
+        state.hash = state.compute_hash();
+        state
+    }
+
+    pub fn grid(&self) -> Grid {
+        Grid::from(self.board)
+    }
+    pub fn result(&self) -> GameResult {
+        self.board.result(self.rules)
+    }
+    /// Minigrids the side to move may play in
+    pub fn allowed_mask(&self) -> u16 {
+        let playable = self.board.playable_mask(self.rules);
+        match self.forced {
+            Some(meta) if playable & (1 << meta) != 0 => 1 << meta,
+            _ => playable,
+        }
+    }
+    pub fn legal_moves(&self) -> Vec<Coord> {
+        self.board.moves_in(self.allowed_mask())
+    }
+    /// Same as `legal_moves`, without allocating
+    pub fn move_list(&self) -> MoveList {
+        self.board.move_list_in(self.allowed_mask())
+    }
+    pub fn legal_move_count(&self) -> u32 {
+        self.board.count_moves_in(self.allowed_mask())
+    }
+    /// Uniformly random legal move, `None` if there are no legal moves
+    pub fn random_legal_move<R: Rng>(&self, rng: &mut R) -> Option<Coord> {
+        self.board.random_move_in(self.allowed_mask(), rng)
+    }
+
+    /// Play `coord` for the side to move; the move must be legal
+    pub fn play(&mut self, coord: Coord) {
+        self.history[self.history_len as usize] = (coord, self.forced);
+        self.history_len += 1;
+        let status = zobrist::status_key(&self.board);
+        self.board.make_move(coord, self.side_to_move);
+        self.hash ^= status ^ zobrist::status_key(&self.board);
+        self.hash ^= zobrist::side_key(self.side_to_move) ^ zobrist::forced_key(self.forced);
+        self.hash ^= zobrist::cell_key(coord, self.side_to_move);
+
+        // The opponent is sent to the minigrid matching the cell just played, if it's still playable
+        let target = coord.cell_index() as u8;
+        self.forced = if self.board.playable_mask(self.rules) & (1 << target) != 0 {
+            Some(target)
+        } else {
+            None
+        };
+        self.side_to_move = opponent(self.side_to_move);
+        self.hash ^= zobrist::side_key(self.side_to_move) ^ zobrist::forced_key(self.forced);
+        self.ply += 1;
+    }
+    /// Take back the last move, returning it (`None` if there is no history to undo)
+    pub fn undo(&mut self) -> Option<Coord> {
+        if self.history_len == 0 {
+            return None;
+        }
+        self.ply -= 1;
+        self.history_len -= 1;
+        let (coord, forced) =
+            std::mem::replace(&mut self.history[self.history_len as usize], NO_MOVE);
+        let status = zobrist::status_key(&self.board);
+        self.board.unmake_move(coord);
+        self.hash ^= status ^ zobrist::status_key(&self.board);
+        self.hash ^= zobrist::side_key(self.side_to_move) ^ zobrist::forced_key(self.forced);
+        self.forced = forced;
+        self.side_to_move = opponent(self.side_to_move);
+        self.hash ^= zobrist::side_key(self.side_to_move) ^ zobrist::forced_key(self.forced);
+        self.hash ^= zobrist::cell_key(coord, self.side_to_move);
+        Some(coord)
+    }
+
+    /// Recompute `hash` from scratch
+    pub fn compute_hash(&self) -> u64 {
+        zobrist::position_key(&self.board, self.side_to_move, self.forced)
+    }
+
+    pub fn last_move(&self) -> Option<Coord> {
+        self.history().next_back()
+    }
+    /// Moves played so far, oldest first
+    pub fn history(&self) -> impl DoubleEndedIterator<Item = Coord> + '_ {
+        self.history[..self.history_len as usize]
+            .iter()
+            .map(|&(coord, _)| coord)
+    }
+}
+
+pub fn opponent(symbol: Cell) -> Cell {
+    match symbol {
+        Cell::Cross => Cell::Circle,
+        Cell::Circle => Cell::Cross,
+        _ => panic!("Invalid player"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::random_game;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn undo_restores_every_previous_position_and_hash() {
+        let mut rng = StdRng::seed_from_u64(4);
+        for (name, rules) in Ruleset::PRESETS {
+            for _ in 0..200 {
+                let positions = random_game(rules, &mut rng);
+                for position in &positions {
+                    assert_eq!(position.hash, position.compute_hash(), "{name}");
+                }
+                let mut state = *positions.last().unwrap();
+                for previous in positions.iter().rev().skip(1) {
+                    state.undo().unwrap();
+                    assert_eq!(state, *previous, "{name}");
+                    assert_eq!(state.hash, state.compute_hash(), "{name}");
+                }
+                assert_eq!(state.undo(), None);
+            }
+        }
+    }
+}
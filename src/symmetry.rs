@@ -0,0 +1,214 @@
+use crate::bitboard::BitBoard;
+use crate::defs::{Coord, Grid};
+use crate::state::GameState;
+use crate::zobrist;
+
+/// One of the 8 symmetries of the square, applied at the same time to the meta board and every minigrid
+#[derive(Clone, Copy, Debug, PartialEq, Hash, Eq)]
+pub enum Symmetry {
+    Identity,
+    /// Clockwise rotations
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    /// Mirror left-right
+    FlipHorizontal,
+    /// Mirror top-bottom
+    FlipVertical,
+    /// Mirror along the top-left to bottom-right diagonal
+    FlipDiagonal,
+    /// Mirror along the top-right to bottom-left diagonal
+    FlipAntiDiagonal,
+}
+
+pub const ALL_SYMMETRIES: [Symmetry; 8] = [
+    Symmetry::Identity,
+    Symmetry::Rotate90,
+    Symmetry::Rotate180,
+    Symmetry::Rotate270,
+    Symmetry::FlipHorizontal,
+    Symmetry::FlipVertical,
+    Symmetry::FlipDiagonal,
+    Symmetry::FlipAntiDiagonal,
+];
+
+impl Symmetry {
+    /// Maps (x, y) of a 3x3 board, with x the column and y the row
+    #[inline]
+    pub fn apply_xy(self, x: u8, y: u8) -> (u8, u8) {
+        match self {
+            Symmetry::Identity => (x, y),
+            Symmetry::Rotate90 => (2 - y, x),
+            Symmetry::Rotate180 => (2 - x, 2 - y),
+            Symmetry::Rotate270 => (y, 2 - x),
+            Symmetry::FlipHorizontal => (2 - x, y),
+            Symmetry::FlipVertical => (x, 2 - y),
+            Symmetry::FlipDiagonal => (y, x),
+            Symmetry::FlipAntiDiagonal => (2 - y, 2 - x),
+        }
+    }
+    /// Maps the index `x + 3 * y` of a 3x3 board
+    #[inline]
+    pub fn apply_index(self, index: usize) -> usize {
+        let (x, y) = self.apply_xy((index % 3) as u8, (index / 3) as u8);
+        (x + 3 * y) as usize
+    }
+    /// The symmetry undoing this one
+    pub fn inverse(self) -> Symmetry {
+        match self {
+            Symmetry::Rotate90 => Symmetry::Rotate270,
+            Symmetry::Rotate270 => Symmetry::Rotate90,
+            s => s,
+        }
+    }
+
+    pub fn apply_coord(self, coord: Coord) -> Coord {
+        let (meta_x, meta_y) = self.apply_xy(coord.meta_x, coord.meta_y);
+        let (x, y) = self.apply_xy(coord.x, coord.y);
+        Coord {
+            meta_x,
+            meta_y,
+            x,
+            y,
+        }
+    }
+    /// Maps a 9-bit mask of a 3x3 board
+    pub fn apply_mask(self, mut mask: u16) -> u16 {
+        let mut out = 0;
+        while mask != 0 {
+            let index = mask.trailing_zeros() as usize;
+            mask &= mask - 1;
+            out |= 1 << self.apply_index(index);
+        }
+        out
+    }
+    /// Maps the forced minigrid constraint of a `GameState`
+    pub fn apply_forced(self, forced: Option<u8>) -> Option<u8> {
+        forced.map(|meta| self.apply_index(meta as usize) as u8)
+    }
+    /// Maps the last move, which is the constraint used by `Grid::get_legal_moves`
+    pub fn apply_last_move(self, last_move: Option<Coord>) -> Option<Coord> {
+        last_move.map(|coord| self.apply_coord(coord))
+    }
+
+    pub fn apply_board(self, board: &BitBoard) -> BitBoard {
+        let mut out = BitBoard::default();
+        for player in 0..2 {
+            for meta in 0..9 {
+                out.cells[player][self.apply_index(meta)] =
+                    self.apply_mask(board.cells[player][meta]);
+            }
+            out.won[player] = self.apply_mask(board.won[player]);
+        }
+        out.drawn = self.apply_mask(board.drawn);
+        out
+    }
+    pub fn apply_grid(self, grid: &Grid) -> Grid {
+        let mut out = Grid::default();
+        for meta in 0..9 {
+            let target = self.apply_index(meta);
+            for cell in 0..9 {
+                out.matrix[target].matrix[self.apply_index(cell)] = grid.matrix[meta].matrix[cell];
+            }
+            out.completed_minigrid[target] = grid.completed_minigrid[meta];
+        }
+        out
+    }
+    /// Maps board, forced minigrid and history of `state`
+    pub fn apply_state(self, state: &GameState) -> GameState {
+        let mut out = *state;
+        out.board = self.apply_board(&state.board);
+        out.forced = self.apply_forced(state.forced);
+        for entry in out.history[..state.history_len as usize].iter_mut() {
+            *entry = (self.apply_coord(entry.0), self.apply_forced(entry.1));
+        }
+        out.hash = out.compute_hash();
+        out
+    }
+}
+
+/// Position key shared by all the symmetric variants of `state`
+pub fn canonical_key(state: &GameState) -> u64 {
+    ALL_SYMMETRIES
+        .iter()
+        .map(|s| {
+            zobrist::position_key(
+                &s.apply_board(&state.board),
+                state.side_to_move,
+                s.apply_forced(state.forced),
+            )
+        })
+        .min()
+        .unwrap()
+}
+/// Representative of the symmetric variants of `state` (the one with the smallest key),
+/// together with the symmetry mapping `state` to it
+pub fn canonicalize(state: &GameState) -> (GameState, Symmetry) {
+    ALL_SYMMETRIES
+        .iter()
+        .map(|&s| (s.apply_state(state), s))
+        .min_by_key(|(transformed, _)| transformed.hash)
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::Ruleset;
+    use crate::testutil::random_position;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::HashSet;
+
+    #[test]
+    fn canonical_key_is_shared_by_all_images() {
+        let mut rng = StdRng::seed_from_u64(8);
+        for (_, rules) in Ruleset::PRESETS {
+            for _ in 0..100 {
+                let state = random_position(rules, rng.gen_range(0..60), &mut rng);
+                let key = canonical_key(&state);
+                for s in ALL_SYMMETRIES {
+                    assert_eq!(canonical_key(&s.apply_state(&state)), key, "{s:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn inverse_undoes_every_symmetry() {
+        let mut rng = StdRng::seed_from_u64(8);
+        for _ in 0..100 {
+            let state = random_position(Ruleset::STANDARD, rng.gen_range(0..60), &mut rng);
+            for s in ALL_SYMMETRIES {
+                assert_eq!(
+                    s.inverse().apply_state(&s.apply_state(&state)),
+                    state,
+                    "{s:?}"
+                );
+                for index in 0..9 {
+                    assert_eq!(s.inverse().apply_index(s.apply_index(index)), index);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn legal_moves_map_through_apply_coord() {
+        let mut rng = StdRng::seed_from_u64(8);
+        for (_, rules) in Ruleset::PRESETS {
+            for _ in 0..100 {
+                let state = random_position(rules, rng.gen_range(0..60), &mut rng);
+                for s in ALL_SYMMETRIES {
+                    let mapped: HashSet<Coord> = state
+                        .legal_moves()
+                        .into_iter()
+                        .map(|c| s.apply_coord(c))
+                        .collect();
+                    let image: HashSet<Coord> =
+                        s.apply_state(&state).legal_moves().into_iter().collect();
+                    assert_eq!(mapped, image, "{s:?}");
+                }
+            }
+        }
+    }
+}
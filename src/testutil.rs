@@ -0,0 +1,39 @@
+//! Positions and helpers shared by the tests of several modules
+
+use crate::rules::Ruleset;
+use crate::state::GameState;
+use rand::rngs::StdRng;
+
+pub use crate::perft::{EMPTY, ENDGAME};
+
+/// X won the top-left minigrid, O has a stone in each of the minigrids of the left column
+pub const X_WON_TOP_LEFT: &str = "XXX....../O......../........./O......../........./O......../........./........./......... X........ X -";
+/// X won the top-left and top-middle minigrids and threatens the top-right one at its top-right cell;
+/// O won the bottom-left minigrid and the bottom-middle one is drawn
+pub const X_TO_WIN: &str = "XXXOO..../XXXOO..../XX.OOX..X/O......../O......../O......../OOOXX..../XOXXOOOXX/O........ XX....O#. X -";
+/// Same threat with O to move in the top-right minigrid, where every move but the block sends X to a
+/// decided minigrid, so X could play anywhere and win
+pub const O_TO_BLOCK: &str = "XXXOO..../XXXOO..../XX.OOX..X/O......../O......../O......../OOOXX..../XOXXOOOXX/......... XX....O#. O 2";
+
+/// Positions of a random game from the empty board, in order, the last one being over
+pub fn random_game(rules: Ruleset, rng: &mut StdRng) -> Vec<GameState> {
+    let mut state = GameState::new(rules);
+    let mut positions = vec![state];
+    while !state.result().is_over() {
+        state.play(state.random_legal_move(rng).unwrap());
+        positions.push(state);
+    }
+    positions
+}
+
+/// Position after `plies` random moves from the empty board, or at the end of the game if it comes first
+pub fn random_position(rules: Ruleset, plies: u32, rng: &mut StdRng) -> GameState {
+    let mut state = GameState::new(rules);
+    for _ in 0..plies {
+        if state.result().is_over() {
+            break;
+        }
+        state.play(state.random_legal_move(rng).unwrap());
+    }
+    state
+}
@@ -0,0 +1,471 @@
+use crate::clock::TimeControl;
+use crate::defs::{Cell, GameResult, Player};
+use crate::mcts::MCTSPlayer;
+use crate::record::result_str;
+use crate::rules::Ruleset;
+use crate::runner::{Adjudication, MatchRunner, MatchStats};
+use crate::state::GameState;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Engine taking part in a tournament; a new player is built for every game, so games can run in parallel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlayerConfig {
+    Mcts {
+        exploration_weight: f32,
+        simulation_steps: u32,
+        time_managed: bool,
+    },
+}
+
+impl PlayerConfig {
+    pub fn build(&self) -> Box<dyn Player> {
+        match *self {
+            PlayerConfig::Mcts {
+                exploration_weight,
+                simulation_steps,
+                time_managed: false,
+            } => Box::new(MCTSPlayer::new(exploration_weight, simulation_steps)),
+            PlayerConfig::Mcts {
+                exploration_weight,
+                simulation_steps,
+                time_managed: true,
+            } => Box::new(MCTSPlayer::time_managed(
+                exploration_weight,
+                simulation_steps,
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlayerConfigParseError(pub String);
+
+impl fmt::Display for PlayerConfigParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid player '{}', expected 'mcts:<exploration weight>:<simulation steps>[:timed]'",
+            self.0
+        )
+    }
+}
+impl std::error::Error for PlayerConfigParseError {}
+
+/// Written as `mcts:<exploration weight>:<simulation steps>`, followed by `:timed` for time-managed players
+impl fmt::Display for PlayerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlayerConfig::Mcts {
+                exploration_weight,
+                simulation_steps,
+                time_managed,
+            } => {
+                write!(f, "mcts:{exploration_weight}:{simulation_steps}")?;
+                if *time_managed {
+                    write!(f, ":timed")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+impl FromStr for PlayerConfig {
+    type Err = PlayerConfigParseError;
+
+    fn from_str(text: &str) -> Result<PlayerConfig, PlayerConfigParseError> {
+        let error = || PlayerConfigParseError(text.to_string());
+        match text.split(':').collect::<Vec<_>>().as_slice() {
+            ["mcts", exploration_weight, simulation_steps, flags @ ..] => Ok(PlayerConfig::Mcts {
+                exploration_weight: exploration_weight.parse().map_err(|_| error())?,
+                simulation_steps: simulation_steps.parse().map_err(|_| error())?,
+                time_managed: match flags {
+                    [] => false,
+                    ["timed"] => true,
+                    _ => return Err(error()),
+                },
+            }),
+            _ => Err(error()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum TournamentKind {
+    /// Every player meets every other player
+    #[default]
+    RoundRobin,
+    /// The first player meets every other player, who don't play each other
+    Gauntlet,
+}
+
+/// One game of a tournament, with the indices of the players in `Tournament::players`
+/// and of the opening in `Tournament::openings`
+#[derive(Clone, Debug)]
+pub struct GameOutcome {
+    pub cross: usize,
+    pub circle: usize,
+    pub opening: usize,
+    pub stats: MatchStats,
+}
+
+pub struct Tournament {
+    pub players: Vec<PlayerConfig>,
+    pub kind: TournamentKind,
+    /// Games every pairing plays with each color and opening
+    pub rounds: u32,
+    pub rules: Ruleset,
+    /// Start positions, which every pairing plays twice with reversed colors (see `crate::openings`)
+    pub openings: Vec<GameState>,
+    pub time_control: TimeControl,
+    /// Time a player can go over its clock before losing on time, see `MatchRunner::overstep_grace`
+    pub overstep_grace: Duration,
+    pub adjudication: Adjudication,
+    /// Print a line for every finished game
+    pub progress: bool,
+}
+
+impl Tournament {
+    /// One round from the empty board, with no clock, no adjudication and no progress output
+    pub fn new(players: Vec<PlayerConfig>, kind: TournamentKind, rules: Ruleset) -> Tournament {
+        Tournament {
+            players,
+            kind,
+            rounds: 1,
+            rules,
+            openings: vec![GameState::new(rules)],
+            time_control: TimeControl::None,
+            overstep_grace: MatchRunner::new(rules).overstep_grace,
+            adjudication: Adjudication::default(),
+            progress: false,
+        }
+    }
+
+    /// Every game to play as (X, O, opening) indices, each pairing playing every opening once with each color
+    /// per round
+    pub fn pairings(&self) -> Vec<(usize, usize, usize)> {
+        let n = self.players.len();
+        let pairs: Vec<(usize, usize)> = match self.kind {
+            TournamentKind::RoundRobin => (0..n)
+                .flat_map(|i| (i + 1..n).map(move |j| (i, j)))
+                .collect(),
+            TournamentKind::Gauntlet => (1..n).map(|j| (0, j)).collect(),
+        };
+        let mut pairings = Vec::new();
+        for _ in 0..self.rounds {
+            for &(i, j) in &pairs {
+                for opening in 0..self.openings.len() {
+                    pairings.extend([(i, j, opening), (j, i, opening)]);
+                }
+            }
+        }
+        pairings
+    }
+
+    /// Play all the games of `pairings` in parallel, returning them in the same order
+    pub fn run(&self) -> Vec<GameOutcome> {
+        let pairings = self.pairings();
+        // Every game gets its own thread pool for the searches: with a shared pool, a thread waiting for the
+        // end of a search could pick up a whole other game, and the player would lose on time
+        let (workers, threads) = self.parallelism(pairings.len());
+        let next = AtomicUsize::new(0);
+        let mut outcomes: Vec<(usize, GameOutcome)> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..workers)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let pool = rayon::ThreadPoolBuilder::new()
+                            .num_threads(threads)
+                            .build()
+                            .expect("failed to build the thread pool of a game");
+                        let mut played = Vec::new();
+                        loop {
+                            let index = next.fetch_add(1, Ordering::Relaxed);
+                            let Some(&pairing) = pairings.get(index) else {
+                                break;
+                            };
+                            let outcome = pool.install(|| self.play(pairing));
+                            if self.progress {
+                                println!(
+                                    "Game {}/{}: #{} {} - #{} {} {}",
+                                    index + 1,
+                                    pairings.len(),
+                                    outcome.cross + 1,
+                                    self.players[outcome.cross],
+                                    outcome.circle + 1,
+                                    self.players[outcome.circle],
+                                    result_str(outcome.stats.result)
+                                );
+                            }
+                            played.push((index, outcome));
+                        }
+                        played
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("a tournament game panicked"))
+                .collect()
+        });
+        outcomes.sort_by_key(|&(index, _)| index);
+        outcomes.into_iter().map(|(_, outcome)| outcome).collect()
+    }
+
+    /// Number of games played at once and of search threads of each game, sharing the threads of the current
+    /// rayon pool; with a clock, no more games than cores are played at once so that they don't lose on time
+    fn parallelism(&self, games: usize) -> (usize, usize) {
+        let threads = rayon::current_num_threads();
+        let mut workers = threads.min(games).max(1);
+        if self.time_control != TimeControl::None {
+            let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+            workers = workers.min(cores);
+        }
+        (workers, (threads / workers).max(1))
+    }
+
+    /// Play one game of `pairings`
+    fn play(&self, (cross, circle, opening): (usize, usize, usize)) -> GameOutcome {
+        let mut runner = MatchRunner::new(self.rules);
+        runner.start = self.openings[opening];
+        runner.time_control = self.time_control;
+        runner.overstep_grace = self.overstep_grace;
+        runner.adjudication = self.adjudication;
+        let stats = runner.run(&self.players[cross].build(), &self.players[circle].build());
+        GameOutcome {
+            cross,
+            circle,
+            opening,
+            stats,
+        }
+    }
+}
+
+/// Wins, draws and losses of every player against every other one
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Crosstable {
+    pub names: Vec<String>,
+    /// `results[i][j]` is (wins, draws, losses) of player `i` against player `j`
+    pub results: Vec<Vec<(u32, u32, u32)>>,
+}
+
+impl Crosstable {
+    pub fn new(names: Vec<String>, games: &[GameOutcome]) -> Crosstable {
+        let n = names.len();
+        let mut results = vec![vec![(0, 0, 0); n]; n];
+        for game in games {
+            let (x, o) = (game.cross, game.circle);
+            match game.stats.result {
+                GameResult::Win(Cell::Cross) => {
+                    results[x][o].0 += 1;
+                    results[o][x].2 += 1;
+                }
+                GameResult::Win(_) => {
+                    results[o][x].0 += 1;
+                    results[x][o].2 += 1;
+                }
+                _ => {
+                    results[x][o].1 += 1;
+                    results[o][x].1 += 1;
+                }
+            }
+        }
+        Crosstable { names, results }
+    }
+
+    /// (wins, draws, losses) of player `i` over the whole tournament
+    pub fn totals(&self, i: usize) -> (u32, u32, u32) {
+        self.results[i]
+            .iter()
+            .fold((0, 0, 0), |(w, d, l), r| (w + r.0, d + r.1, l + r.2))
+    }
+    /// Points (1 per win, 1/2 per draw) of player `i` as a percentage of its games, `None` without games
+    pub fn score_percent(&self, i: usize) -> Option<f64> {
+        let (w, d, l) = self.totals(i);
+        let games = w + d + l;
+        (games > 0).then(|| 100.0 * (w as f64 + 0.5 * d as f64) / games as f64)
+    }
+}
+
+/// One row per player, with its wins-draws-losses against every opponent, its totals and its score
+impl fmt::Display for Crosstable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let n = self.names.len();
+        let width = self.names.iter().map(|s| s.len()).max().unwrap_or(0).max(6);
+        let cell = |(w, d, l): (u32, u32, u32)| format!("{w}-{d}-{l}");
+        let cell_width = self
+            .results
+            .iter()
+            .flatten()
+            .map(|&r| cell(r).len())
+            .max()
+            .unwrap_or(0)
+            .max(5);
+
+        write!(f, "{:>3} {:<width$}", "#", "Player")?;
+        for j in 0..n {
+            write!(f, " {:>cell_width$}", j + 1)?;
+        }
+        writeln!(f, " {:>5} {:>5} {:>5} {:>7}", "W", "D", "L", "Score")?;
+        for i in 0..n {
+            write!(f, "{:>3} {:<width$}", i + 1, self.names[i])?;
+            for j in 0..n {
+                if i == j {
+                    write!(f, " {:>cell_width$}", "-")?;
+                } else {
+                    write!(f, " {:>cell_width$}", cell(self.results[i][j]))?;
+                }
+            }
+            let (w, d, l) = self.totals(i);
+            match self.score_percent(i) {
+                Some(score) => writeln!(f, " {w:>5} {d:>5} {l:>5} {score:>6.1}%")?,
+                None => writeln!(f, " {w:>5} {d:>5} {l:>5} {:>7}", "-")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::Termination;
+    use crate::testutil::random_position;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::collections::HashMap;
+
+    fn player(steps: u32) -> PlayerConfig {
+        PlayerConfig::Mcts {
+            exploration_weight: 1.4,
+            simulation_steps: steps,
+            time_managed: true,
+        }
+    }
+
+    fn in_pool<T: Send>(threads: usize, f: impl FnOnce() -> T + Send) -> T {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap()
+            .install(f)
+    }
+
+    #[test]
+    fn every_pair_plays_each_opening_once_with_each_color_per_round() {
+        let mut tournament = Tournament::new(
+            vec![player(1), player(2), player(3)],
+            TournamentKind::RoundRobin,
+            Ruleset::STANDARD,
+        );
+        tournament.openings = vec![GameState::new(Ruleset::STANDARD); 2];
+        tournament.rounds = 2;
+        let mut counts = HashMap::new();
+        for pairing in tournament.pairings() {
+            *counts.entry(pairing).or_insert(0) += 1;
+        }
+        // 3 pairs, 2 colors and 2 openings
+        assert_eq!(counts.len(), 12);
+        assert!(counts.values().all(|&n| n == 2));
+        assert!(counts.keys().all(|&(x, o, _)| x != o));
+
+        tournament.kind = TournamentKind::Gauntlet;
+        tournament.rounds = 1;
+        let pairings = tournament.pairings();
+        assert_eq!(pairings.len(), 8);
+        assert!(pairings.iter().all(|&(x, o, _)| (x == 0) != (o == 0)));
+    }
+
+    fn outcome(cross: usize, circle: usize, result: GameResult) -> GameOutcome {
+        let final_state = GameState::new(Ruleset::STANDARD);
+        GameOutcome {
+            cross,
+            circle,
+            opening: 0,
+            stats: MatchStats {
+                result,
+                termination: Termination::Normal,
+                number_turns: 0,
+                final_state,
+                a_side: Cell::Cross,
+                moves: Vec::new(),
+            },
+        }
+    }
+
+    #[test]
+    fn crosstable_totals_and_scores() {
+        let games = [
+            outcome(0, 1, GameResult::Win(Cell::Cross)),
+            outcome(1, 0, GameResult::Win(Cell::Cross)),
+            outcome(0, 1, GameResult::Draw),
+            outcome(2, 0, GameResult::Win(Cell::Circle)),
+        ];
+        let names = vec![
+            "a".to_string(),
+            "b".to_string(),
+            "c".to_string(),
+            "d".to_string(),
+        ];
+        let table = Crosstable::new(names, &games);
+        assert_eq!(table.results[0][1], (1, 1, 1));
+        assert_eq!(table.results[1][0], (1, 1, 1));
+        assert_eq!(table.results[0][2], (1, 0, 0));
+        assert_eq!(table.results[2][0], (0, 0, 1));
+        assert_eq!(table.totals(0), (2, 1, 1));
+        assert_eq!(table.totals(1), (1, 1, 1));
+        assert_eq!(table.score_percent(0), Some(62.5));
+        assert_eq!(table.score_percent(1), Some(50.0));
+        assert_eq!(table.score_percent(2), Some(0.0));
+        assert_eq!(table.score_percent(3), None);
+    }
+
+    #[test]
+    fn timed_tournaments_play_at_most_one_game_per_core() {
+        let mut tournament = Tournament::new(
+            vec![player(1), player(1)],
+            TournamentKind::RoundRobin,
+            Ruleset::STANDARD,
+        );
+        assert_eq!(in_pool(4, || tournament.parallelism(10)), (4, 1));
+        assert_eq!(in_pool(4, || tournament.parallelism(2)), (2, 2));
+        assert_eq!(in_pool(4, || tournament.parallelism(0)), (1, 4));
+
+        tournament.time_control = TimeControl::PerMove(Duration::from_millis(20));
+        let cores = std::thread::available_parallelism().unwrap().get();
+        let workers = cores.min(4);
+        assert_eq!(
+            in_pool(4, || tournament.parallelism(10)),
+            (workers, 4 / workers)
+        );
+    }
+
+    #[test]
+    fn timed_games_are_not_lost_on_time() {
+        let mut tournament = Tournament::new(
+            vec![player(100), player(100)],
+            TournamentKind::RoundRobin,
+            Ruleset::STANDARD,
+        );
+        // Openings late in the game keep the test short
+        let mut rng = StdRng::seed_from_u64(17);
+        tournament.openings =
+            std::iter::repeat_with(|| random_position(Ruleset::STANDARD, 40, &mut rng))
+                .filter(|state| !state.result().is_over())
+                .take(3)
+                .collect();
+        tournament.time_control = TimeControl::PerMove(Duration::from_millis(20));
+        // Much more than the delays of a busy machine, much less than a whole game played during a move
+        tournament.overstep_grace = Duration::from_millis(250);
+
+        // More games than threads, so that games wait for a thread while others are searching
+        for game in in_pool(4, || tournament.run()) {
+            assert!(
+                !matches!(game.stats.termination, Termination::TimeForfeit(_)),
+                "{}",
+                game.stats.termination
+            );
+        }
+    }
+}
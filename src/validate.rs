@@ -0,0 +1,341 @@
+use crate::bitboard::{is_win, FULL_MASK};
+use crate::defs::{cell_char, Cell, Coord};
+use crate::state::{opponent, GameState};
+use std::fmt;
+
+/// Reason why a position can't be reached in a legal game
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PositionError {
+    /// A cell is taken by both players
+    OverlappingCells { minigrid: usize },
+    /// X moves first, so it must have as many stones as O or one more
+    WrongStoneCount { crosses: u32, circles: u32 },
+    /// The side to move doesn't match the stone count
+    WrongSideToMove,
+    /// Both players have a line in the same minigrid, which needs `Ruleset::play_in_won_minigrids`
+    TwoMinigridWinners { minigrid: usize },
+    /// Stones were added to a minigrid after it was won, which needs `Ruleset::play_in_won_minigrids`
+    MoveInWonMinigrid { minigrid: usize },
+    /// The won/drawn status of a minigrid doesn't match its cells
+    StatusMismatch { minigrid: usize },
+    /// Both players have a meta line
+    TwoWinners,
+    /// The player with the given side has a meta line, but the game went on after it completed it
+    MoveAfterWin(Cell),
+    /// The forced minigrid is closed, or isn't the one the last move sends to
+    InconsistentForced,
+}
+
+/// Reason why `GameState::try_play` refused a move
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoveError {
+    GameOver,
+    /// Some coordinate isn't between 0 and 2
+    OutOfRange(Coord),
+    /// The move must be played in the minigrid with the given index
+    WrongMinigrid {
+        forced: u8,
+    },
+    /// The minigrid can't be played in anymore
+    ClosedMinigrid(Coord),
+    Occupied(Coord),
+}
+
+impl fmt::Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PositionError::OverlappingCells { minigrid } => {
+                write!(f, "minigrid {minigrid} has cells taken by both players")
+            }
+            PositionError::WrongStoneCount { crosses, circles } => {
+                write!(f, "impossible stone count: {crosses} X and {circles} O")
+            }
+            PositionError::WrongSideToMove => write!(f, "wrong side to move"),
+            PositionError::TwoMinigridWinners { minigrid } => {
+                write!(f, "both players have a line in minigrid {minigrid}")
+            }
+            PositionError::MoveInWonMinigrid { minigrid } => {
+                write!(f, "minigrid {minigrid} was played in after being won")
+            }
+            PositionError::StatusMismatch { minigrid } => {
+                write!(f, "status of minigrid {minigrid} doesn't match its cells")
+            }
+            PositionError::TwoWinners => write!(f, "both players have a meta line"),
+            PositionError::MoveAfterWin(winner) => {
+                write!(f, "moves were played after {} won", cell_char(*winner))
+            }
+            PositionError::InconsistentForced => {
+                write!(f, "forced minigrid doesn't match the last move")
+            }
+        }
+    }
+}
+impl std::error::Error for PositionError {}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MoveError::GameOver => write!(f, "the game is over"),
+            MoveError::OutOfRange(_) => write!(f, "coordinates must be between 0-2"),
+            MoveError::WrongMinigrid { forced } => write!(
+                f,
+                "the move must be played in minigrid ({},{})",
+                forced % 3,
+                forced / 3
+            ),
+            MoveError::ClosedMinigrid(coord) => write!(f, "the minigrid of {coord} is closed"),
+            MoveError::Occupied(coord) => write!(f, "{coord} is already taken"),
+        }
+    }
+}
+impl std::error::Error for MoveError {}
+
+impl GameState {
+    /// Check that the position can be reached in a legal game under `self.rules`
+    pub fn validate(&self) -> Result<(), PositionError> {
+        let board = &self.board;
+        let open_won = self.rules.play_in_won_minigrids;
+        let mut crosses = 0;
+        let mut circles = 0;
+
+        for meta in 0..9 {
+            let (own_x, own_o) = (board.cells[0][meta], board.cells[1][meta]);
+            if own_x & own_o != 0 {
+                return Err(PositionError::OverlappingCells { minigrid: meta });
+            }
+            crosses += own_x.count_ones();
+            circles += own_o.count_ones();
+
+            let status = board.minigrid_status(meta);
+            let status_ok = match (is_win(own_x), is_win(own_o)) {
+                (true, true) if !open_won => {
+                    return Err(PositionError::TwoMinigridWinners { minigrid: meta })
+                }
+                // Whoever completed a line first keeps the minigrid
+                (true, true) => status == Cell::Cross || status == Cell::Circle,
+                (true, false) => status == Cell::Cross,
+                (false, true) => status == Cell::Circle,
+                _ if own_x | own_o == FULL_MASK => status == Cell::Draw,
+                _ => status == Cell::Empty,
+            };
+            if !status_ok {
+                return Err(PositionError::StatusMismatch { minigrid: meta });
+            }
+
+            // The winner's last stone must be the one completing the line
+            if !open_won && (status == Cell::Cross || status == Cell::Circle) {
+                let own = if status == Cell::Cross { own_x } else { own_o };
+                let mut stones = own;
+                let mut has_last_stone = false;
+                while stones != 0 {
+                    let bit = stones & stones.wrapping_neg();
+                    stones &= stones - 1;
+                    has_last_stone |= !is_win(own & !bit);
+                }
+                if !has_last_stone {
+                    return Err(PositionError::MoveInWonMinigrid { minigrid: meta });
+                }
+            }
+        }
+
+        if crosses != circles && crosses != circles + 1 {
+            return Err(PositionError::WrongStoneCount { crosses, circles });
+        }
+        let expected_side = if crosses == circles {
+            Cell::Cross
+        } else {
+            Cell::Circle
+        };
+        if self.side_to_move != expected_side {
+            return Err(PositionError::WrongSideToMove);
+        }
+
+        // The player completing a meta line made the last move (unlike a win on the tiebreak)
+        match board.meta_lines(self.rules) {
+            (true, true) if !self.rules.draw_counts_for_both => {
+                return Err(PositionError::TwoWinners)
+            }
+            (true, false) if self.side_to_move != opponent(Cell::Cross) => {
+                return Err(PositionError::MoveAfterWin(Cell::Cross))
+            }
+            (false, true) if self.side_to_move != opponent(Cell::Circle) => {
+                return Err(PositionError::MoveAfterWin(Cell::Circle))
+            }
+            _ => {}
+        }
+
+        let playable = board.playable_mask(self.rules);
+        let forced_ok = match (self.last_move(), self.forced) {
+            (_, Some(meta)) if playable & (1 << meta) == 0 => false,
+            (Some(last), forced) => {
+                let target = last.cell_index() as u8;
+                let expected = (playable & (1 << target) != 0).then_some(target);
+                forced == expected
+            }
+            (None, _) => true,
+        };
+        if !forced_ok {
+            return Err(PositionError::InconsistentForced);
+        }
+
+        Ok(())
+    }
+
+    /// Play `coord` if it's legal, otherwise leave the state untouched and return why it isn't
+    pub fn try_play(&mut self, coord: Coord) -> Result<(), MoveError> {
+        if self.result().is_over() {
+            return Err(MoveError::GameOver);
+        }
+        if [coord.meta_x, coord.meta_y, coord.x, coord.y]
+            .iter()
+            .any(|&v| v > 2)
+        {
+            return Err(MoveError::OutOfRange(coord));
+        }
+        let meta = coord.meta_index();
+        if self.board.playable_mask(self.rules) & (1 << meta) == 0 {
+            return Err(MoveError::ClosedMinigrid(coord));
+        }
+        if self.allowed_mask() & (1 << meta) == 0 {
+            return Err(MoveError::WrongMinigrid {
+                forced: self.forced.unwrap_or_default(),
+            });
+        }
+        if self.board.occupied(meta) & (1 << coord.cell_index()) != 0 {
+            return Err(MoveError::Occupied(coord));
+        }
+        self.play(coord);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitboard::BitBoard;
+    use crate::notation::{parse_position, NotationError};
+    use crate::rules::Ruleset;
+    use crate::testutil::X_WON_TOP_LEFT;
+
+    fn position_error(text: &str) -> PositionError {
+        match parse_position(text, Ruleset::STANDARD) {
+            Err(NotationError::InvalidPosition(e)) => e,
+            other => panic!("{text} gave {other:?}"),
+        }
+    }
+
+    #[test]
+    fn position_errors() {
+        let mut board = BitBoard::default();
+        board.cells[0][4] = 1;
+        board.cells[1][4] = 1;
+        let state = GameState::from_board(board, Cell::Cross, None, Ruleset::STANDARD);
+        assert_eq!(
+            state.validate(),
+            Err(PositionError::OverlappingCells { minigrid: 4 })
+        );
+
+        let cases = [
+            (
+                "XX......./........./........./........./........./........./........./........./......... ......... X -",
+                PositionError::WrongStoneCount {
+                    crosses: 2,
+                    circles: 0,
+                },
+            ),
+            (
+                "X......../........./........./........./........./........./........./........./......... ......... X -",
+                PositionError::WrongSideToMove,
+            ),
+            (
+                "XXXOOO.../........./........./........./........./........./........./........./......... X........ X -",
+                PositionError::TwoMinigridWinners { minigrid: 0 },
+            ),
+            (
+                "XXXXXX.../OO.OO..../OO......./........./........./........./........./........./......... X........ X -",
+                PositionError::MoveInWonMinigrid { minigrid: 0 },
+            ),
+            (
+                "X......../........./........./........./........./........./........./........./......... X........ O -",
+                PositionError::StatusMismatch { minigrid: 0 },
+            ),
+            (
+                "XXX....../XXX....../XXX....../OOO....../OOO....../OOO....../........./........./......... XXXOOO... X -",
+                PositionError::TwoWinners,
+            ),
+            (
+                "XXX....../XXX....../XXX....../OO.O...../OO.O...../OO.O...../........./........./......... XXX...... X -",
+                PositionError::MoveAfterWin(Cell::Cross),
+            ),
+        ];
+        for (text, error) in cases {
+            assert_eq!(position_error(text), error, "{text}");
+        }
+
+        let mut state = parse_position(X_WON_TOP_LEFT, Ruleset::STANDARD).unwrap();
+        state.forced = Some(0);
+        assert_eq!(state.validate(), Err(PositionError::InconsistentForced));
+        state.forced = Some(1);
+        assert_eq!(state.validate(), Ok(()));
+        state.play(Coord::from_indices(1, 4));
+        state.forced = Some(1);
+        assert_eq!(state.validate(), Err(PositionError::InconsistentForced));
+    }
+
+    #[test]
+    fn won_game_is_valid_with_the_loser_to_move() {
+        let won = "XXX....../XXX....../XXX....../OO.O...../OO.O...../OO......./........./........./......... XXX...... O -";
+        let state = parse_position(won, Ruleset::STANDARD).unwrap();
+        assert_eq!(state.result(), crate::defs::GameResult::Win(Cell::Cross));
+    }
+
+    #[test]
+    fn move_errors_leave_the_state_untouched() {
+        let won = "XXX....../XXX....../XXX....../OO.O...../OO.O...../OO......./........./........./......... XXX...... O -";
+        let e5 = Coord::from_indices(4, 4);
+        let mut after_e5 = GameState::new(Ruleset::STANDARD);
+        after_e5.play(e5);
+        let cases = [
+            (
+                parse_position(won, Ruleset::STANDARD).unwrap(),
+                Coord::from_indices(8, 0),
+                MoveError::GameOver,
+            ),
+            (
+                GameState::new(Ruleset::STANDARD),
+                Coord {
+                    meta_x: 3,
+                    meta_y: 0,
+                    x: 0,
+                    y: 0,
+                },
+                MoveError::OutOfRange(Coord {
+                    meta_x: 3,
+                    meta_y: 0,
+                    x: 0,
+                    y: 0,
+                }),
+            ),
+            (
+                after_e5,
+                Coord::from_indices(0, 0),
+                MoveError::WrongMinigrid { forced: 4 },
+            ),
+            (
+                parse_position(X_WON_TOP_LEFT, Ruleset::STANDARD).unwrap(),
+                Coord::from_indices(0, 5),
+                MoveError::ClosedMinigrid(Coord::from_indices(0, 5)),
+            ),
+            (after_e5, e5, MoveError::Occupied(e5)),
+        ];
+        for (state, coord, error) in cases {
+            let mut played = state;
+            assert_eq!(played.try_play(coord), Err(error));
+            assert_eq!(played, state, "{error:?}");
+        }
+
+        let mut played = after_e5;
+        assert_eq!(played.try_play(Coord::from_indices(4, 0)), Ok(()));
+        assert_eq!(played.last_move(), Some(Coord::from_indices(4, 0)));
+    }
+}
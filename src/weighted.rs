@@ -1,25 +1,10 @@
 use cached::proc_macro::cached;
 
-use crate::defs::{Cell, Coord, Grid, Player};
-
-const CENTER_COORD: Coord = Coord {
-    meta_x: 1,
-    meta_y: 1,
-    x: 1,
-    y: 1,
-};
-const LINES_3: [[usize; 3]; 8] = [
-    [0, 1, 2],
-    [3, 4, 5],
-    [6, 7, 8],
-    [0, 3, 6],
-    [1, 4, 7],
-    [2, 5, 8],
-    [0, 4, 8],
-    [2, 4, 6],
-];
+use crate::defs::{Cell, Coord, Player};
+use crate::game::MetaGame;
+use crate::state::GameState;
 
-#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Default)]
 pub struct WeightedParameters {
     pub take_cell: i32,          // 0~1.0    (*10**9 for integer rappresentation)
     pub take_double_cell: i32,   // 0~1.0
@@ -42,15 +27,13 @@ pub struct WeightedPlayer {
     pub weighted_params: WeightedParameters,
     pub apply_softsign: bool,
     pub ignore_giving: bool,
-
-    pub symbol: Cell,
 }
 
 impl WeightedPlayer {
-    pub fn new(params: WeightedParameters, symbol: Cell) -> Self {
+    /// The player plays whichever side is to move in the positions it's given
+    pub fn new(params: WeightedParameters) -> Self {
         Self {
             weighted_params: params,
-            symbol,
             apply_softsign: false,
             ignore_giving: false,
         }
@@ -60,53 +43,34 @@ impl WeightedPlayer {
 #[cached]
 /// This function is needed as WeightedParameters store parameters as i32 even tho they are f32 values.
 fn convert(v: i32) -> f32 {
-    return (v * 1000000000) as f32;
-}
-
-fn is_win_in_cells(cells: &[Cell; 9], player: Cell) -> bool {
-    for line in &LINES_3 {
-        if cells[line[0]] == player && cells[line[1]] == player && cells[line[2]] == player {
-            return true;
-        }
-    }
-    false
+    v as f32 / 1_000_000_000.0
 }
 
-/// Returns true if there exists a line with exactly 2 `player` and 1 `Empty`.
-fn has_completable_two_in_row(cells: &[Cell; 9], player: Cell) -> bool {
-    for line in &LINES_3 {
-        let a = cells[line[0]];
-        let b = cells[line[1]];
-        let c = cells[line[2]];
-        let count_player = (a == player) as usize + (b == player) as usize + (c == player) as usize;
-        let count_empty =
-            (a == Cell::Empty) as usize + (b == Cell::Empty) as usize + (c == Cell::Empty) as usize;
-        if count_player == 2 && count_empty == 1 {
-            return true;
-        }
-    }
-    false
-}
-
-#[cached]
+/// Cached by the Zobrist key of `state` instead of hashing the whole board
+#[cached(
+    key = "(WeightedParameters, u64, Coord, Cell)",
+    convert = r#"{ (params, state.hash, eval_move, player_symbol) }"#
+)]
 fn eval_board(
     params: WeightedParameters,
-    grid: Grid,
+    state: &GameState,
     eval_move: Coord,
     player_symbol: Cell,
 ) -> f32 {
-    let mut score: f32 = 0.0;
-    let meta_x: usize = eval_move.meta_x as usize;
-    let meta_y: usize = eval_move.meta_y as usize;
-    let x: usize = eval_move.x as usize;
-    let y: usize = eval_move.y as usize;
-    let target_cell = grid.matrix[meta_x].matrix[meta_y];
+    eval_meta_move(params, state, eval_move, player_symbol)
+}
 
-    let mut hypothetical_cells = grid.matrix[meta_x].matrix;
-    hypothetical_cells[x] = player_symbol;
+/// Same as `eval_board` on any board size, without the cache
+fn eval_meta_move<G: MetaGame>(
+    params: WeightedParameters,
+    state: &G,
+    eval_move: G::Move,
+    player_symbol: Cell,
+) -> f32 {
+    let mut score: f32 = 0.0;
 
     // Apply take_cell
-    if is_win_in_cells(&hypothetical_cells, player_symbol) {
+    if state.completes_line(eval_move, player_symbol) {
         score += convert(params.take_cell);
     }
 
@@ -123,9 +87,14 @@ fn eval_board(
     // Apply stop_double_grid
 
     // Apply play_corner, _sides and _center
-    if eval_move == CENTER_COORD {
+    let n = state.size();
+    let (meta, cell) = state.move_indices(eval_move);
+    let (x, y) = (cell % n, cell / n);
+    // Center cell of the center minigrid, which only exists on odd sizes
+    let center = n / 2 * (n + 1);
+    if n % 2 == 1 && meta == center && cell == center {
         score += convert(params.play_center)
-    } else if (eval_move.x == 0 || eval_move.x == 2) && (eval_move.y == 0 || eval_move.y == 2) {
+    } else if (x == 0 || x == n - 1) && (y == 0 || y == n - 1) {
         score += convert(params.play_corner)
     } else {
         score += convert(params.play_sides)
@@ -139,7 +108,7 @@ fn eval_board(
 
     // NOTE: best_enemy_move is not applied by this function
 
-    return score;
+    score
 }
 
 impl Player for WeightedPlayer {
@@ -147,12 +116,59 @@ impl Player for WeightedPlayer {
         // No reset logic needed
     }
 
-    fn select_move(
-        &self,
-        _grid: Grid,
-        _legal_moves: Vec<Coord>,
-        _last_move: Option<Coord>,
-    ) -> Coord {
-        todo!("Implement WeightedPlayer");
+    fn name(&self) -> String {
+        String::from("Weighted")
+    }
+
+    /// Legal move with the highest weighted score
+    fn select_move(&self, state: &GameState) -> Coord {
+        state
+            .legal_moves()
+            .into_iter()
+            .map(|coord| {
+                let score = eval_board(self.weighted_params, state, coord, state.side_to_move);
+                (coord, score)
+            })
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(coord, _)| coord)
+            .expect("no legal moves")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notation::parse_position;
+    use crate::rules::Ruleset;
+    use crate::testutil::X_TO_WIN;
+
+    #[test]
+    fn takes_the_cell_that_wins_the_minigrid() {
+        let state = parse_position(X_TO_WIN, Ruleset::STANDARD).unwrap();
+        let player = WeightedPlayer::new(WeightedParameters {
+            take_cell: 1_000_000_000,
+            ..WeightedParameters::default()
+        });
+        assert_eq!(player.select_move(&state), Coord::from_indices(2, 2));
+    }
+
+    #[test]
+    fn prefers_the_center_then_corners() {
+        let state = GameState::new(Ruleset::STANDARD);
+        let params = WeightedParameters {
+            play_center: 1_000_000_000,
+            play_corner: 500_000_000,
+            ..WeightedParameters::default()
+        };
+        assert_eq!(
+            WeightedPlayer::new(params).select_move(&state),
+            Coord::from_indices(4, 4)
+        );
+        let corner = WeightedPlayer::new(WeightedParameters {
+            play_center: 0,
+            ..params
+        })
+        .select_move(&state);
+        assert!([0, 2, 6, 8].contains(&corner.cell_index()));
     }
 }
@@ -0,0 +1,153 @@
+use crate::bitboard::BitBoard;
+use crate::defs::{Cell, Coord};
+
+/// Small PRNG used to fill the key tables at compile time
+const fn splitmix64(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    (state, z ^ (z >> 31))
+}
+
+struct Keys {
+    cells: [[u64; 81]; 2],
+    side: u64,
+    forced: [u64; 9],
+    won: [[u64; 9]; 2],
+    drawn: [u64; 9],
+}
+
+const fn build_keys() -> Keys {
+    let mut keys = Keys {
+        cells: [[0; 81]; 2],
+        side: 0,
+        forced: [0; 9],
+        won: [[0; 9]; 2],
+        drawn: [0; 9],
+    };
+    let mut seed = 0x0DDB_1A5E_5BAD_5EED;
+    let mut i = 0;
+    while i < 81 {
+        let (s, k) = splitmix64(seed);
+        keys.cells[0][i] = k;
+        let (s, k) = splitmix64(s);
+        keys.cells[1][i] = k;
+        seed = s;
+        i += 1;
+    }
+    let mut i = 0;
+    while i < 9 {
+        let (s, k) = splitmix64(seed);
+        keys.forced[i] = k;
+        seed = s;
+        i += 1;
+    }
+    let (s, k) = splitmix64(seed);
+    keys.side = k;
+    seed = s;
+    let mut i = 0;
+    while i < 9 {
+        let (s, k) = splitmix64(seed);
+        keys.won[0][i] = k;
+        let (s, k) = splitmix64(s);
+        keys.won[1][i] = k;
+        let (s, k) = splitmix64(s);
+        keys.drawn[i] = k;
+        seed = s;
+        i += 1;
+    }
+    keys
+}
+static KEYS: Keys = build_keys();
+
+/// Key of `symbol` occupying the cell `coord`
+#[inline]
+pub fn cell_key(coord: Coord, symbol: Cell) -> u64 {
+    let index = coord.meta_index() * 9 + coord.cell_index();
+    match symbol {
+        Cell::Cross => KEYS.cells[0][index],
+        Cell::Circle => KEYS.cells[1][index],
+        _ => 0,
+    }
+}
+/// Key toggled when Circle is to move
+#[inline]
+pub fn side_key(side_to_move: Cell) -> u64 {
+    if side_to_move == Cell::Circle {
+        KEYS.side
+    } else {
+        0
+    }
+}
+/// Key of the minigrid the next move is forced into (0 when the move is free)
+#[inline]
+pub fn forced_key(forced: Option<u8>) -> u64 {
+    match forced {
+        Some(meta) => KEYS.forced[meta as usize],
+        None => 0,
+    }
+}
+
+/// Key of the won and drawn minigrids of `board`, which don't always follow from the cells: with
+/// `Ruleset::play_in_won_minigrids` both players can have a line in a minigrid won by the first one
+pub fn status_key(board: &BitBoard) -> u64 {
+    let mut key = 0;
+    for meta in 0..9 {
+        let bit = 1 << meta;
+        if board.won[0] & bit != 0 {
+            key ^= KEYS.won[0][meta];
+        }
+        if board.won[1] & bit != 0 {
+            key ^= KEYS.won[1][meta];
+        }
+        if board.drawn & bit != 0 {
+            key ^= KEYS.drawn[meta];
+        }
+    }
+    key
+}
+
+/// Key of the cells and minigrid statuses of `board`
+pub fn board_key(board: &BitBoard) -> u64 {
+    let mut key = status_key(board);
+    for (player, masks) in board.cells.iter().enumerate() {
+        for (meta, &mask) in masks.iter().enumerate() {
+            let mut mask = mask;
+            while mask != 0 {
+                let cell = mask.trailing_zeros() as usize;
+                mask &= mask - 1;
+                key ^= KEYS.cells[player][meta * 9 + cell];
+            }
+        }
+    }
+    key
+}
+/// Full key of a position, as maintained incrementally by `GameState::play`
+pub fn position_key(board: &BitBoard, side_to_move: Cell, forced: Option<u8>) -> u64 {
+    board_key(board) ^ side_key(side_to_move) ^ forced_key(forced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn minigrid_owner_changes_the_key() {
+        // Both players have a line in minigrid 0, as allowed by `play_in_won_minigrids`
+        let cells = [
+            [0b000_000_111, 0, 0, 0, 0, 0, 0, 0, 0],
+            [0b111_000_000, 0, 0, 0, 0, 0, 0, 0, 0],
+        ];
+        let cross_first = BitBoard {
+            cells,
+            won: [1, 0],
+            drawn: 0,
+        };
+        let circle_first = BitBoard {
+            won: [0, 1],
+            ..cross_first
+        };
+        assert_ne!(board_key(&cross_first), board_key(&circle_first));
+    }
+}